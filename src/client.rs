@@ -0,0 +1,597 @@
+pub(super) mod args;
+
+use crate::client::args::{
+    ClientArgs, ClientCommand, FetchArgs, LogArgs, ProjectArgs, ReleaseArgs, StatusArgs, SubmitArgs,
+};
+use crate::common::file::{get_frame_file_name, get_video_file_name};
+use crate::common::frame_spec::FrameSpec;
+use crate::common::message::{ClientMessage, ClientResponse};
+use crate::common::net::{read_json, write_json};
+use crate::common::output::OutputOptions;
+use crate::common::overrides::RenderOverrides;
+use crate::common::render_task::{Frame, RenderProgress};
+use crate::common::retry::RetryPolicy;
+use crate::common::settings::ProjectSettings;
+use crate::common::status::{ProjectStatus, WorkerStatus};
+use crate::common::transfer::{recv_file, send_file};
+use crate::common::video::{VideoSettings, VideoState};
+use failure::Fail;
+use log::{debug, info};
+use std::io::{BufReader, BufWriter};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, io, process, thread};
+
+/// How often to check for newly completed frames when following a project
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(5);
+/// The frame rate of videos if none is specified
+const DEFAULT_FRAME_RATE: f64 = 24.0;
+
+pub(super) type ClientResult<T> = Result<T, ClientError>;
+
+#[derive(Fail, Debug)]
+pub(super) enum ClientError {
+    #[fail(display = "Error connecting to server: {}", 0)]
+    ConnectFailed(#[fail(cause)] io::Error),
+    #[fail(display = "I/O error: {}", 0)]
+    IoError(#[fail(cause)] io::Error),
+    #[fail(display = "Error transferring file: {}", 0)]
+    TransferFailed(#[fail(cause)] io::Error),
+    #[fail(display = "Project file not found: {:?}", 0)]
+    ProjectFileMissing(PathBuf),
+    #[fail(display = "Server error: {}", 0)]
+    ServerError(String),
+    #[fail(display = "Unexpected response: {:?}", 0)]
+    UnexpectedResponse(Box<ClientResponse>),
+}
+
+pub(super) struct Client<'a> {
+    reader: BufReader<&'a TcpStream>,
+    writer: BufWriter<&'a TcpStream>,
+}
+
+impl<'a> Client<'a> {
+    /// Connect to the server and run a command
+    pub(super) fn run(args: ClientArgs) -> ClientResult<()> {
+        debug!("Connecting to {}:{}...", args.address, args.port);
+
+        // Attempt to open a connection to the server
+        let stream = TcpStream::connect((args.address.as_str(), args.port))
+            .map_err(ClientError::ConnectFailed)?;
+
+        let mut client =
+            Client { reader: BufReader::new(&stream), writer: BufWriter::new(&stream) };
+
+        // Run the command
+        match args.command {
+            ClientCommand::Submit(args) => client.submit(args),
+            ClientCommand::List => client.list(),
+            ClientCommand::Status(args) => client.status(args),
+            ClientCommand::Retry(args) => client.retry(args),
+            ClientCommand::Pause(args) => client.pause(args),
+            ClientCommand::Resume(args) => client.resume(args),
+            ClientCommand::Cancel(args) => client.cancel(args),
+            ClientCommand::Remove(args) => client.remove(args),
+            ClientCommand::Fetch(args) => client.fetch(args),
+            ClientCommand::Log(args) => client.log(args),
+            ClientCommand::Workers => client.workers(),
+            ClientCommand::Release(args) => client.release(args),
+        }
+    }
+
+    /// Submit a project to the server
+    fn submit(&mut self, args: SubmitArgs) -> ClientResult<()> {
+        if let Some(file) = args.file.as_ref().filter(|file| !file.is_file()) {
+            return Err(ClientError::ProjectFileMissing(file.clone()));
+        }
+        // If no name has been specified, use the name of the project file (the server uses the
+        // name of the other project if the file is shared)
+        let file_name = args.file.as_ref().map(|file| file.file_stem().unwrap_or_default());
+        let name = args
+            .name
+            .or_else(|| file_name.map(|file_name| file_name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        match &args.from {
+            Some(from) if name.is_empty() => info!("Submitting project from \"{}\"...", from),
+            _ => info!("Submitting project \"{}\"...", name),
+        }
+
+        // Send the project information
+        let frame_rate = args.frame_rate.unwrap_or(DEFAULT_FRAME_RATE);
+        self.write_message(ClientMessage::SubmitProject(Box::new(ProjectSettings {
+            name,
+            output_ext: args.format,
+            output_options: OutputOptions {
+                color_depth: args.color_depth,
+                exr_codec: args.exr_codec,
+                quality: args.quality,
+            },
+            frames: args.frames.to_string(),
+            retry_policy: RetryPolicy {
+                max_attempts: args.max_attempts,
+                retry_delay: Duration::from_secs(args.retry_delay),
+            },
+            priority: args.priority,
+            weight: args.weight,
+            chunk_size: args.chunk_size,
+            frame_order: args.order,
+            overrides: RenderOverrides {
+                scene: args.overrides.scene,
+                view_layer: args.overrides.view_layer,
+                camera: args.overrides.camera,
+                resolution_x: args.overrides.resolution_x,
+                resolution_y: args.overrides.resolution_y,
+                resolution_percentage: args.overrides.resolution_percentage,
+                samples: args.overrides.samples,
+                engine: args.overrides.engine,
+                denoise: match (args.overrides.denoise, args.overrides.no_denoise) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+            },
+            shared_file: args.from,
+            video: args.video.map(|codec| VideoSettings { codec, frame_rate }),
+        })))?;
+        if let Some(file) = &args.file {
+            // Wait for the server to accept the project
+            match self.read_response()? {
+                ClientResponse::UploadReady => (),
+                response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+            }
+            // Upload the project file
+            info!("Uploading project file {:?}...", file);
+            send_file(&mut self.reader, &mut self.writer, file)
+                .map_err(ClientError::TransferFailed)?;
+        }
+        // Wait for the server to add the project
+        match self.read_response()? {
+            ClientResponse::ProjectAdded(project_uuid) => {
+                info!("Project submitted: {}", project_uuid);
+                Ok(())
+            }
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// List the projects on the server
+    fn list(&mut self) -> ClientResult<()> {
+        self.write_message(ClientMessage::ListProjects)?;
+        match self.read_response()? {
+            ClientResponse::Projects(projects) => {
+                for project in projects {
+                    println!(
+                        "{}  {}  {}/{} frames ({:.0}%){}",
+                        project.uuid,
+                        project.name,
+                        project.num_completed,
+                        project.num_frames(),
+                        project.progress * 100.0,
+                        format_state(&project, " [", "]")
+                    );
+                }
+                Ok(())
+            }
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Show the status of a project, or of all projects
+    fn status(&mut self, args: StatusArgs) -> ClientResult<()> {
+        match args.project {
+            // Show the detailed status of a single project
+            Some(project) => {
+                let project = self.project_status(project)?;
+                if args.json {
+                    println!("{}", serde_json::to_string_pretty(&project).unwrap());
+                } else {
+                    print_project_status(&project);
+                }
+            }
+            // Show a table with the status of every project
+            None => {
+                self.write_message(ClientMessage::ListProjects)?;
+                match self.read_response()? {
+                    ClientResponse::Projects(projects) if args.json => {
+                        println!("{}", serde_json::to_string_pretty(&projects).unwrap());
+                    }
+                    ClientResponse::Projects(projects) => print_status_table(&projects),
+                    response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Retry the failed frames of a project
+    fn retry(&mut self, args: ProjectArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::RetryFailed { project: args.project })?;
+        self.expect_done()?;
+        info!("Retrying failed frames");
+        Ok(())
+    }
+
+    /// Pause a project
+    fn pause(&mut self, args: ProjectArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::PauseProject { project: args.project })?;
+        self.expect_done()?;
+        info!("Project paused");
+        Ok(())
+    }
+
+    /// Resume a paused project
+    fn resume(&mut self, args: ProjectArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::ResumeProject { project: args.project })?;
+        self.expect_done()?;
+        info!("Project resumed");
+        Ok(())
+    }
+
+    /// Cancel a project
+    fn cancel(&mut self, args: ProjectArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::CancelProject { project: args.project })?;
+        self.expect_done()?;
+        info!("Project cancelled");
+        Ok(())
+    }
+
+    /// Remove a project and its files from the server
+    fn remove(&mut self, args: ProjectArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::RemoveProject { project: args.project })?;
+        self.expect_done()?;
+        info!("Project removed");
+        Ok(())
+    }
+
+    /// Show the render log of a frame
+    fn log(&mut self, args: LogArgs) -> ClientResult<()> {
+        // Download to a temporary file if the log should be printed
+        let log_file = match &args.output {
+            Some(output) => output.clone(),
+            None => env::temp_dir().join(format!("tinyrf-{}.log", process::id())),
+        };
+        self.write_message(ClientMessage::FetchLog { project: args.project, frame: args.frame })?;
+        match self.read_response()? {
+            ClientResponse::OutputReady => (),
+            response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+        // Remove any existing file so that the transfer is not resumed from it
+        if log_file.is_file() {
+            fs::remove_file(&log_file)?;
+        }
+        recv_file(&mut self.reader, &mut self.writer, &log_file)
+            .map_err(ClientError::TransferFailed)?;
+        if args.output.is_none() {
+            io::copy(&mut fs::File::open(&log_file)?, &mut io::stdout())?;
+            fs::remove_file(&log_file)?;
+        }
+        Ok(())
+    }
+
+    /// List the workers that have connected to the server
+    fn workers(&mut self) -> ClientResult<()> {
+        self.write_message(ClientMessage::ListWorkers)?;
+        match self.read_response()? {
+            ClientResponse::Workers(workers) => {
+                print_worker_table(&workers);
+                Ok(())
+            }
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Let a quarantined worker receive tasks again
+    fn release(&mut self, args: ReleaseArgs) -> ClientResult<()> {
+        self.write_message(ClientMessage::ReleaseWorker { worker: args.worker })?;
+        self.expect_done()?;
+        info!("Worker released");
+        Ok(())
+    }
+
+    /// Download the completed frames of a project
+    fn fetch(&mut self, args: FetchArgs) -> ClientResult<()> {
+        // Create the destination directory if it does not exist
+        if !args.dest_dir.is_dir() {
+            fs::create_dir_all(&args.dest_dir)?;
+        }
+        let mut project = self.project_status(args.project)?;
+        info!("Fetching frames of \"{}\"...", project.name);
+        loop {
+            // Download the frames that have been completed since the last check
+            for &frame in &project.completed_frames {
+                self.fetch_frame(&project, frame, &args.dest_dir)?;
+            }
+            // Download the video once it has been encoded
+            if project.video_state == Some(VideoState::Finished) {
+                self.fetch_video(&project, &args.dest_dir)?;
+            }
+            // Stop once there are no more frames that could be completed (or a video that could
+            // be encoded)
+            let finished = project.cancelled || project.num_waiting + project.num_assigned == 0;
+            let encoding = project.video_state == Some(VideoState::Encoding)
+                || (project.video.is_some()
+                    && project.video_state.is_none()
+                    && project.num_completed == project.num_frames());
+            if !args.follow || (finished && project.num_assigned == 0 && !encoding) {
+                break;
+            }
+            thread::sleep(FOLLOW_INTERVAL);
+            project = self.project_status(project.uuid.to_string())?;
+        }
+        info!(
+            "Fetched {}/{} frames of \"{}\"",
+            project.num_completed,
+            project.num_frames(),
+            project.name
+        );
+        Ok(())
+    }
+
+    /// Download the output of a single frame, unless it is already present
+    fn fetch_frame(
+        &mut self,
+        project: &ProjectStatus,
+        frame: Frame,
+        dest_dir: &Path,
+    ) -> ClientResult<()> {
+        let file_name = get_frame_file_name(frame, project.output_ext);
+        let output_file = dest_dir.join(&file_name);
+        // Skip frames that have already been downloaded
+        if output_file.is_file() {
+            debug!("Skipping {:?} (already downloaded)", &file_name);
+            return Ok(());
+        }
+        info!("Downloading {:?}...", &file_name);
+        self.write_message(ClientMessage::FetchOutput {
+            project: project.uuid.to_string(),
+            frame,
+        })?;
+        match self.read_response()? {
+            ClientResponse::OutputReady => (),
+            response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+        // Download to a partial file first, so that interrupted downloads are resumed
+        let partial_file = dest_dir.join(format!("{}.part", file_name));
+        recv_file(&mut self.reader, &mut self.writer, &partial_file)
+            .map_err(ClientError::TransferFailed)?;
+        Ok(fs::rename(partial_file, output_file)?)
+    }
+
+    /// Download the video of a project, unless it is already present
+    fn fetch_video(&mut self, project: &ProjectStatus, dest_dir: &Path) -> ClientResult<()> {
+        let codec = match &project.video {
+            Some(settings) => settings.codec,
+            None => return Ok(()),
+        };
+        let file_name = get_video_file_name(codec);
+        let video_file = dest_dir.join(&file_name);
+        // Skip the video if it has already been downloaded
+        if video_file.is_file() {
+            debug!("Skipping {:?} (already downloaded)", &file_name);
+            return Ok(());
+        }
+        info!("Downloading {:?}...", &file_name);
+        self.write_message(ClientMessage::FetchVideo { project: project.uuid.to_string() })?;
+        match self.read_response()? {
+            ClientResponse::OutputReady => (),
+            response => return Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+        // Download to a partial file first, so that interrupted downloads are resumed
+        let partial_file = dest_dir.join(format!("{}.part", file_name));
+        recv_file(&mut self.reader, &mut self.writer, &partial_file)
+            .map_err(ClientError::TransferFailed)?;
+        Ok(fs::rename(partial_file, video_file)?)
+    }
+
+    /// Get the status of a project
+    fn project_status(&mut self, project: String) -> ClientResult<ProjectStatus> {
+        self.write_message(ClientMessage::ProjectStatus { project })?;
+        match self.read_response()? {
+            ClientResponse::Project(project) => Ok(*project),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Wait for the server to report that a request was completed
+    fn expect_done(&mut self) -> ClientResult<()> {
+        match self.read_response()? {
+            ClientResponse::Done => Ok(()),
+            response => Err(ClientError::UnexpectedResponse(Box::new(response))),
+        }
+    }
+
+    /// Read a response from the server (blocking), converting errors
+    fn read_response(&mut self) -> ClientResult<ClientResponse> {
+        let response = read_json(&mut self.reader)?;
+        debug!("Server -> {:?}", &response);
+        match response {
+            ClientResponse::Error(error) => Err(ClientError::ServerError(error)),
+            response => Ok(response),
+        }
+    }
+
+    /// Send a message to the server
+    fn write_message(&mut self, message: ClientMessage) -> io::Result<()> {
+        debug!("Server <- {:?}", &message);
+        write_json(&mut self.writer, message)
+    }
+}
+
+/// Print a table with a row for each project
+fn print_status_table(projects: &[ProjectStatus]) {
+    let name_width = projects.iter().map(|project| project.name.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:<8}  {:<width$}  {:>8}  {:>6}  {:>6}  {:>7}  {:>9}  {:>9}  {:>6}  {:>8}",
+        "UUID",
+        "NAME",
+        "PRIORITY",
+        "WEIGHT",
+        "FRAMES",
+        "WAITING",
+        "RENDERING",
+        "COMPLETED",
+        "FAILED",
+        "PROGRESS",
+        width = name_width
+    );
+    for project in projects {
+        println!(
+            "{:<8}  {:<width$}  {:>8}  {:>6}  {:>6}  {:>7}  {:>9}  {:>9}  {:>6}  {:>7.1}%{}",
+            &project.uuid.to_string()[..8],
+            project.name,
+            project.priority,
+            project.weight,
+            project.num_frames(),
+            project.num_waiting,
+            project.num_assigned,
+            project.num_completed,
+            project.num_failed,
+            project.progress * 100.0,
+            format_state(project, "  (", ")"),
+            width = name_width
+        );
+    }
+}
+
+/// Print a table with a row for each worker
+fn print_worker_table(workers: &[WorkerStatus]) {
+    let name_width = workers.iter().map(|worker| worker.name.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:<width$}  {:>9}  {:>9}  {:>6}  {:>8}",
+        "NAME",
+        "CONNECTED",
+        "COMPLETED",
+        "FAILED",
+        "IN A ROW",
+        width = name_width
+    );
+    for worker in workers {
+        println!(
+            "{:<width$}  {:>9}  {:>9}  {:>6}  {:>8}{}",
+            worker.name,
+            worker.connections,
+            worker.num_completed,
+            worker.num_failed,
+            worker.consecutive_failures,
+            if worker.quarantined { "  (quarantined)" } else { "" },
+            width = name_width
+        );
+    }
+}
+
+/// Print the detailed status of a project
+fn print_project_status(project: &ProjectStatus) {
+    println!("Project:   {} ({})", project.name, project.uuid);
+    if project.output_options.is_empty() {
+        println!("Format:    {}", project.output_ext);
+    } else {
+        println!("Format:    {} ({})", project.output_ext, project.output_options);
+    }
+    if let Some(shared_file) = project.shared_file {
+        println!("File:      shared with project {}", shared_file);
+    }
+    println!("Frames:    {} ({} order)", project.frame_spec, project.frame_order);
+    println!("Priority:  {} (weight {})", project.priority, project.weight);
+    if !project.overrides.is_empty() {
+        println!("Overrides: {}", project.overrides);
+    }
+    if project.chunk_size > 1 {
+        println!("Chunks:    {} frames per task", project.chunk_size);
+    }
+    if let Some(video) = &project.video {
+        match &project.video_state {
+            Some(state) => println!("Video:     {} ({})", video, state),
+            None => println!("Video:     {} (once every frame is rendered)", video),
+        }
+    }
+    println!(
+        "Progress:  {}/{} frames ({:.1}%){}",
+        project.num_completed,
+        project.num_frames(),
+        project.progress * 100.0,
+        format_state(project, " (", ")")
+    );
+    if let Some(eta) = project.eta {
+        println!("ETA:       {}", format_duration(eta));
+    }
+    println!("Waiting:   {}", format_frames(&project.waiting_frames));
+    println!("Rendering:");
+    for assigned in &project.assigned_frames {
+        let progress = assigned.progress.as_ref().map(format_progress).unwrap_or_default();
+        match &assigned.worker {
+            Some(worker) if progress.is_empty() => println!("  {:>6}  {}", assigned.frame, worker),
+            Some(worker) => println!("  {:>6}  {}  {}", assigned.frame, worker, progress),
+            None => println!("  {:>6}  (queued)", assigned.frame),
+        }
+    }
+    println!("Completed: {}", format_frames(&project.completed_frames));
+    println!("Failed:    {}", format_frames(&project.failed_frames));
+    println!("Failed attempts (maximum of {} per frame):", project.retry_policy.max_attempts);
+    for (frame, attempts) in &project.attempts {
+        for attempt in attempts {
+            let worker = attempt.worker.as_deref().unwrap_or("(unknown)");
+            match &attempt.failure {
+                Some(failure) => {
+                    println!("  {:>6}  {}: {}", frame, worker, failure);
+                    // Show the last line of stderr, which usually contains the error
+                    if let Some(line) = failure.stderr_tail.last() {
+                        println!("  {:>6}    {}", "", line);
+                    }
+                }
+                None => println!("  {:>6}  {}", frame, worker),
+            }
+        }
+    }
+}
+
+/// Format the state of a project if it is not active, surrounded by a prefix and suffix
+fn format_state(project: &ProjectStatus, prefix: &str, suffix: &str) -> String {
+    match project.state_label() {
+        Some(label) => format!("{}{}{}", prefix, label, suffix),
+        None => String::new(),
+    }
+}
+
+/// Format the progress of a render (e.g. "45%, 1m 02s left, peak memory 1.2G")
+fn format_progress(progress: &RenderProgress) -> String {
+    let mut parts = Vec::new();
+    if let Some(fraction) = progress.fraction {
+        parts.push(format!("{:.0}%", fraction * 100.0));
+    }
+    if let Some(remaining) = progress.remaining {
+        parts.push(format!("{} left", format_duration(remaining)));
+    }
+    if let Some(peak_memory) = &progress.peak_memory {
+        parts.push(format!("peak memory {}", peak_memory));
+    }
+    parts.join(", ")
+}
+
+/// Format a duration in hours, minutes and seconds (e.g. "1h 02m 03s")
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Format a list of frames as a compact list of ranges (e.g. "1-10,12,15-20")
+fn format_frames(frames: &[Frame]) -> String {
+    if frames.is_empty() {
+        return String::from("-");
+    }
+    FrameSpec::from_frames(frames.iter().copied()).to_string()
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
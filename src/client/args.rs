@@ -0,0 +1,197 @@
+use crate::common::frame_spec::FrameSpec;
+use crate::common::output::ExrCodec;
+use crate::common::overrides::RenderEngine;
+use crate::common::render_task::{FileExt, Frame};
+use crate::common::settings::FrameOrder;
+use crate::common::video::VideoCodec;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub(crate) struct ClientArgs {
+    /// Server address
+    #[structopt(short = "a", long = "address", default_value = "localhost")]
+    pub address: String,
+    /// Server port
+    #[structopt(short = "p", long = "port", default_value = "4049")]
+    pub port: u16,
+    #[structopt(subcommand)]
+    pub command: ClientCommand,
+}
+
+// The arguments are only parsed once, so the size of the variants does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(StructOpt)]
+pub(crate) enum ClientCommand {
+    /// Submits a project to the server
+    Submit(SubmitArgs),
+    /// Lists the projects on the server
+    List,
+    /// Shows the status of a project, or of all projects
+    Status(StatusArgs),
+    /// Retries the failed frames of a project
+    Retry(ProjectArgs),
+    /// Stops assigning the frames of a project until it is resumed
+    Pause(ProjectArgs),
+    /// Resumes a paused project
+    Resume(ProjectArgs),
+    /// Cancels a project, aborting the frames being rendered
+    Cancel(ProjectArgs),
+    /// Cancels a project and deletes its files from the server
+    Remove(ProjectArgs),
+    /// Downloads the completed frames of a project
+    Fetch(FetchArgs),
+    /// Shows the render log of a frame
+    Log(LogArgs),
+    /// Lists the workers that have connected to the server
+    Workers,
+    /// Lets a quarantined worker receive tasks again
+    Release(ReleaseArgs),
+}
+
+#[derive(StructOpt)]
+pub(crate) struct SubmitArgs {
+    /// Project file
+    #[structopt(name = "FILE", parse(from_os_str), required_unless = "from")]
+    pub file: Option<PathBuf>,
+    /// Render the project file already uploaded for another project (UUID, UUID prefix or name)
+    /// instead of uploading a file, e.g. to render another scene of the same file
+    #[structopt(long = "from", conflicts_with = "FILE")]
+    pub from: Option<String>,
+    /// Frames to render: comma-separated frames, ranges and ranges with a step
+    /// (e.g. "1-250" or "1-100x5,120,130-140")
+    #[structopt(short = "f", long = "frames", default_value = "1")]
+    pub frames: FrameSpec,
+    /// Output format: bmp, rgb, png, jpg, jp2, tga, exr, multilayer-exr (with every render pass
+    /// in a separate layer), hdr, tif or webp
+    #[structopt(short = "F", long = "format", default_value = "png")]
+    pub format: FileExt,
+    /// Bits per color channel: 8 or 16 for png, rgb and tif, 8, 12 or 16 for jp2, and 16 (half
+    /// float) or 32 (full float) for exr
+    #[structopt(long = "color-depth")]
+    pub color_depth: Option<u32>,
+    /// Compression of exr files: none, pxr24, zip, piz, rle, zips, b44, b44a, dwaa or dwab
+    #[structopt(long = "exr-codec")]
+    pub exr_codec: Option<ExrCodec>,
+    /// Quality of jpg, jp2 and webp files, from 0 to 100
+    #[structopt(long = "quality")]
+    pub quality: Option<u32>,
+    /// Project name (defaults to the name of the project file, or of the project it is from)
+    #[structopt(short = "n", long = "name")]
+    pub name: Option<String>,
+    /// Maximum number of times to render a frame before marking it as failed
+    #[structopt(long = "max-attempts", default_value = "3")]
+    pub max_attempts: u32,
+    /// Seconds to wait before rendering a failed frame again
+    #[structopt(long = "retry-delay", default_value = "0")]
+    pub retry_delay: u64,
+    /// Priority of the project, from -10 to 10 (higher priority projects are rendered first)
+    #[structopt(long = "priority", default_value = "0", allow_hyphen_values = true)]
+    pub priority: i32,
+    /// Share of the workers the project gets relative to projects of the same priority
+    #[structopt(long = "weight", default_value = "1")]
+    pub weight: u32,
+    /// Number of consecutive frames rendered by a worker in one go (speeds up light scenes by
+    /// loading the project file once per chunk, cannot be used with a step in the frames)
+    #[structopt(short = "c", long = "chunk-size", default_value = "1")]
+    pub chunk_size: Frame,
+    /// Order to render the frames in: sequential, or progressive to render a rough version of
+    /// the whole sequence first (the first and last frames, then the middle, then the quarters...)
+    #[structopt(long = "order", default_value = "sequential")]
+    pub order: FrameOrder,
+    /// Encode the frames into a video once they have all been rendered: h264 (MP4), prores
+    /// (QuickTime) or gif (animated preview)
+    #[structopt(long = "video")]
+    pub video: Option<VideoCodec>,
+    /// Frame rate of the video in frames per second [default: 24]
+    #[structopt(long = "frame-rate", requires = "video")]
+    pub frame_rate: Option<f64>,
+    #[structopt(flatten)]
+    pub overrides: OverrideArgs,
+}
+
+/// Render settings that replace the ones saved in the project file
+#[derive(StructOpt)]
+pub(crate) struct OverrideArgs {
+    /// Scene to render instead of the active scene
+    #[structopt(long = "scene")]
+    pub scene: Option<String>,
+    /// Only view layer to render (defaults to every view layer enabled for rendering)
+    #[structopt(long = "view-layer")]
+    pub view_layer: Option<String>,
+    /// Name of the camera object to render with
+    #[structopt(long = "camera")]
+    pub camera: Option<String>,
+    /// Horizontal resolution in pixels
+    #[structopt(long = "resolution-x")]
+    pub resolution_x: Option<u32>,
+    /// Vertical resolution in pixels
+    #[structopt(long = "resolution-y")]
+    pub resolution_y: Option<u32>,
+    /// Percentage of the resolution to render at (e.g. 50 for a quick preview)
+    #[structopt(long = "resolution-percentage")]
+    pub resolution_percentage: Option<u32>,
+    /// Number of samples per pixel
+    #[structopt(long = "samples")]
+    pub samples: Option<u32>,
+    /// Render engine (cycles, eevee or workbench)
+    #[structopt(long = "engine")]
+    pub engine: Option<RenderEngine>,
+    /// Turn denoising on (Cycles only)
+    #[structopt(long = "denoise", conflicts_with = "no-denoise")]
+    pub denoise: bool,
+    /// Turn denoising off (Cycles only)
+    #[structopt(long = "no-denoise")]
+    pub no_denoise: bool,
+}
+
+#[derive(StructOpt)]
+pub(crate) struct ProjectArgs {
+    /// Project UUID (or a prefix of it) or name
+    #[structopt(name = "PROJECT")]
+    pub project: String,
+}
+
+#[derive(StructOpt)]
+pub(crate) struct LogArgs {
+    /// Project UUID (or a prefix of it) or name
+    #[structopt(name = "PROJECT")]
+    pub project: String,
+    /// Frame number
+    #[structopt(name = "FRAME")]
+    pub frame: Frame,
+    /// Save the log to a file instead of printing it
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt)]
+pub(crate) struct ReleaseArgs {
+    /// Worker name, as shown by the workers command
+    #[structopt(name = "WORKER")]
+    pub worker: String,
+}
+
+#[derive(StructOpt)]
+pub(crate) struct StatusArgs {
+    /// Project UUID (or a prefix of it) or name
+    #[structopt(name = "PROJECT")]
+    pub project: Option<String>,
+    /// Print the status as JSON
+    #[structopt(long = "json")]
+    pub json: bool,
+}
+
+#[derive(StructOpt)]
+pub(crate) struct FetchArgs {
+    /// Project UUID (or a prefix of it) or name
+    #[structopt(name = "PROJECT")]
+    pub project: String,
+    /// Directory to download the frames to
+    #[structopt(name = "DEST", parse(from_os_str))]
+    pub dest_dir: PathBuf,
+    /// Keep downloading frames as they are completed until the project is finished (and its
+    /// video has been encoded)
+    #[structopt(long = "follow")]
+    pub follow: bool,
+}
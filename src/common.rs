@@ -1,5 +1,12 @@
 pub(crate) mod file;
+pub(crate) mod frame_spec;
 pub(crate) mod message;
 pub(crate) mod net;
+pub(crate) mod output;
+pub(crate) mod overrides;
 pub(crate) mod render_task;
+pub(crate) mod retry;
+pub(crate) mod settings;
+pub(crate) mod status;
 pub(crate) mod transfer;
+pub(crate) mod video;
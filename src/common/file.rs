@@ -1,4 +1,5 @@
-use crate::common::render_task::RenderTask;
+use crate::common::render_task::{FileExt, Frame, RenderTask};
+use crate::common::video::VideoCodec;
 use log::debug;
 use std::env::temp_dir;
 use std::fs::create_dir_all;
@@ -17,6 +18,25 @@ pub(crate) fn init_working_dir(prefix: &str) -> io::Result<PathBuf> {
     Ok(working_dir)
 }
 
+/// Open (creating it if necessary) a persistent working directory
+pub(crate) fn open_data_dir(data_dir: &Path) -> io::Result<PathBuf> {
+    create_dir_all(data_dir)?;
+    let data_dir = data_dir.canonicalize()?;
+
+    debug!("Working directory set to {:?}", &data_dir);
+    Ok(data_dir)
+}
+
+/// Get the path to the file containing the saved server state
+pub(crate) fn get_state_file(working_dir: &Path) -> PathBuf {
+    working_dir.join("state.json")
+}
+
+/// Get the path to the log of every attempt at sending an event to a webhook
+pub(crate) fn get_webhook_log_file(working_dir: &Path) -> PathBuf {
+    working_dir.join("webhooks.log")
+}
+
 /// Get the path to the directory for the specified project
 pub(crate) fn get_project_dir(working_dir: &Path, project_uuid: &Uuid) -> PathBuf {
     working_dir.join(project_uuid.to_string())
@@ -27,8 +47,41 @@ pub(crate) fn get_project_file(working_dir: &Path, project_uuid: &Uuid) -> PathB
     get_project_dir(working_dir, project_uuid).join("project.blend")
 }
 
-/// Get the path to the output file for the specified render task
-pub(crate) fn get_output_file(working_dir: &Path, render_task: &RenderTask) -> PathBuf {
-    get_project_dir(working_dir, &render_task.project_uuid)
-        .join(format!("{:04}.{}", render_task.frame, render_task.output_ext))
+/// Get the path to the output file for the specified frame of a project
+pub(crate) fn get_frame_file(
+    working_dir: &Path,
+    project_uuid: &Uuid,
+    frame: Frame,
+    output_ext: FileExt,
+) -> PathBuf {
+    get_project_dir(working_dir, project_uuid).join(get_frame_file_name(frame, output_ext))
+}
+
+/// Get the path to the render log for the specified render task (named after its first frame)
+pub(crate) fn get_log_file(working_dir: &Path, render_task: &RenderTask) -> PathBuf {
+    get_frame_log_file(working_dir, &render_task.project_uuid, render_task.start_frame)
+}
+
+/// Get the path to the render log for the specified frame of a project
+pub(crate) fn get_frame_log_file(working_dir: &Path, project_uuid: &Uuid, frame: Frame) -> PathBuf {
+    get_project_dir(working_dir, project_uuid).join(format!("{:04}.log", frame))
+}
+
+/// Get the file name of the output file for the specified frame
+pub(crate) fn get_frame_file_name(frame: Frame, output_ext: FileExt) -> String {
+    format!("{:04}.{}", frame, output_ext.extension())
+}
+
+/// Get the path to the video encoded from the frames of a project
+pub(crate) fn get_video_file(
+    working_dir: &Path,
+    project_uuid: &Uuid,
+    codec: VideoCodec,
+) -> PathBuf {
+    get_project_dir(working_dir, project_uuid).join(get_video_file_name(codec))
+}
+
+/// Get the file name of the video encoded from the frames of a project
+pub(crate) fn get_video_file_name(codec: VideoCodec) -> String {
+    format!("video.{}", codec.extension())
 }
@@ -0,0 +1,234 @@
+use crate::common::render_task::Frame;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// The highest frame number Blender can render
+pub(crate) const MAX_FRAME: Frame = 1_048_574;
+
+/// A specification of the frames to render, made of comma-separated parts that are either a
+/// single frame ("120"), a range of frames ("130-140") or every nth frame of a range ("1-100x5")
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct FrameSpec {
+    parts: Vec<FrameSpecPart>,
+}
+
+/// The frames from start to end (inclusive), every step frames
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct FrameSpecPart {
+    start: Frame,
+    end: Frame,
+    step: Frame,
+}
+
+impl FrameSpec {
+    /// Create a specification that covers exactly the specified frames
+    pub(crate) fn from_frames(frames: impl IntoIterator<Item = Frame>) -> FrameSpec {
+        let frames: BTreeSet<Frame> = frames.into_iter().collect();
+        let mut parts: Vec<FrameSpecPart> = Vec::new();
+        for frame in frames {
+            match parts.last_mut() {
+                Some(part) if part.end + 1 == frame => part.end = frame,
+                _ => parts.push(FrameSpecPart { start: frame, end: frame, step: 1 }),
+            }
+        }
+        FrameSpec { parts }
+    }
+
+    /// Get the frames covered by the specification, in order and without duplicates
+    pub(crate) fn frames(&self) -> Vec<Frame> {
+        let frames: BTreeSet<Frame> = self
+            .parts
+            .iter()
+            .flat_map(|part| (part.start..=part.end).step_by(part.step as usize))
+            .collect();
+        frames.into_iter().collect()
+    }
+
+    /// Check whether the specification skips frames within a range (e.g. "1-100x5")
+    pub(crate) fn has_steps(&self) -> bool {
+        self.parts.iter().any(|part| part.step > 1 && part.start + part.step <= part.end)
+    }
+
+    /// Check whether the specification covers no frames
+    pub(crate) fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+impl fmt::Display for FrameSpecPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else if self.step == 1 {
+            write!(f, "{}-{}", self.start, self.end)
+        } else {
+            write!(f, "{}-{}x{}", self.start, self.end, self.step)
+        }
+    }
+}
+
+impl fmt::Display for FrameSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, part) in self.parts.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FrameSpecPart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_frame = |s: &str| match s.trim().parse() {
+            Ok(frame) if frame <= MAX_FRAME => Ok(frame),
+            Ok(_) => Err(format!("frame {} is after the last frame ({})", s.trim(), MAX_FRAME)),
+            Err(_) => Err(format!("invalid frame: {}", s.trim())),
+        };
+        // Split off the step, if any
+        let (range, step) = match s.find('x') {
+            Some(index) => {
+                let step = &s[index + 1..];
+                let step = step.trim().parse().map_err(|_| format!("invalid step: {}", step))?;
+                (&s[..index], step)
+            }
+            None => (s, 1),
+        };
+        if step == 0 {
+            return Err(String::from("the step must be at least 1"));
+        }
+        // Accept either a single frame or a range of frames
+        let (start, end) = match range.find('-') {
+            Some(index) => (parse_frame(&range[..index])?, parse_frame(&range[index + 1..])?),
+            None => (parse_frame(range)?, parse_frame(range)?),
+        };
+        if start > end {
+            return Err(format!("start frame {} is after end frame {}", start, end));
+        }
+        Ok(FrameSpecPart { start, end, step })
+    }
+}
+
+impl FromStr for FrameSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(String::from("no frames specified"));
+        }
+        let parts = s.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        Ok(FrameSpec { parts })
+    }
+}
+
+impl TryFrom<String> for FrameSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FrameSpec> for String {
+    fn from(frame_spec: FrameSpec) -> Self {
+        frame_spec.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(s: &str) -> Vec<Frame> {
+        s.parse::<FrameSpec>().unwrap().frames()
+    }
+
+    #[test]
+    fn parse_single_frames_and_ranges() {
+        assert_eq!(frames("7"), vec![7]);
+        assert_eq!(frames("3-6"), vec![3, 4, 5, 6]);
+        assert_eq!(frames("5-5"), vec![5]);
+    }
+
+    #[test]
+    fn parse_steps() {
+        assert_eq!(frames("1-100x5"), (1..=100).step_by(5).collect::<Vec<_>>());
+        assert_eq!(frames("1-10x3"), vec![1, 4, 7, 10]);
+        assert_eq!(frames("1-10x20"), vec![1]);
+        assert_eq!(frames("4x2"), vec![4]);
+    }
+
+    #[test]
+    fn detect_steps() {
+        let has_steps = |s: &str| s.parse::<FrameSpec>().unwrap().has_steps();
+        assert!(has_steps("1-10x2"));
+        assert!(has_steps("1-3,5-20x5"));
+        assert!(!has_steps("1-10,20-30"));
+        // Steps that never skip a frame in the range do not count
+        assert!(!has_steps("1-10x20"));
+        assert!(!has_steps("4x2"));
+    }
+
+    #[test]
+    fn parse_lists() {
+        assert_eq!(frames("120,1-3,130-140x5"), vec![1, 2, 3, 120, 130, 135, 140]);
+    }
+
+    #[test]
+    fn deduplicate_overlapping_parts() {
+        assert_eq!(frames("1-5,3-8"), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(frames("1-10x2,1-10x3,5"), vec![1, 3, 4, 5, 7, 9, 10]);
+        assert_eq!(frames("2,2,2"), vec![2]);
+    }
+
+    #[test]
+    fn ignore_surrounding_whitespace() {
+        assert_eq!(frames(" 1 - 3 , 5 "), vec![1, 2, 3, 5]);
+        assert_eq!(frames("1-10 x 4"), vec![1, 5, 9]);
+    }
+
+    #[test]
+    fn reject_invalid_specs() {
+        assert!("".parse::<FrameSpec>().is_err());
+        assert!("  ".parse::<FrameSpec>().is_err());
+        assert!("1-10x0".parse::<FrameSpec>().is_err());
+        assert!("10-1".parse::<FrameSpec>().is_err());
+        assert!("1,,2".parse::<FrameSpec>().is_err());
+        assert!("1,".parse::<FrameSpec>().is_err());
+        assert!("a-5".parse::<FrameSpec>().is_err());
+        assert!("1-5xb".parse::<FrameSpec>().is_err());
+        assert!("-5".parse::<FrameSpec>().is_err());
+    }
+
+    #[test]
+    fn check_max_frame() {
+        assert_eq!(frames(&MAX_FRAME.to_string()), vec![MAX_FRAME]);
+        assert!(format!("1-{}", MAX_FRAME + 1).parse::<FrameSpec>().is_err());
+        assert!((MAX_FRAME + 1).to_string().parse::<FrameSpec>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in &["7", "3-6", "1-100x5", "120,1-3,130-140x5"] {
+            let spec: FrameSpec = s.parse().unwrap();
+            assert_eq!(&spec.to_string(), s);
+            assert_eq!(spec.to_string().parse::<FrameSpec>().unwrap(), spec);
+        }
+    }
+
+    #[test]
+    fn from_frames_round_trip() {
+        let spec = FrameSpec::from_frames(vec![8, 1, 2, 3, 5, 7, 2]);
+        assert_eq!(spec.to_string(), "1-3,5,7-8");
+        assert_eq!(spec.frames(), vec![1, 2, 3, 5, 7, 8]);
+        assert_eq!(frames(&spec.to_string()), spec.frames());
+        assert!(FrameSpec::from_frames(Vec::new()).is_empty());
+    }
+}
@@ -1,5 +1,20 @@
-use crate::common::render_task::{RenderTask, RenderTaskResult};
+use crate::common::render_task::{Frame, RenderProgress, RenderTask, RenderTaskResult};
+use crate::common::settings::ProjectSettings;
+use crate::common::status::{ProjectStatus, WorkerStatus};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often a worker sends a heartbeat to the server while rendering
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The first message sent by a peer, used to tell workers and clients apart
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum PeerMessage {
+    Worker(WorkerMessage),
+    Client(ClientMessage),
+}
 
 /// A message sent from the server to the worker
 #[derive(Debug, Deserialize, Serialize)]
@@ -8,6 +23,11 @@ pub(crate) enum ServerMessage {
     Idle,
     /// Start a new render
     StartRender(RenderTask),
+    /// Stop the current render
+    AbortRender,
+    /// The render result was received and the server is ready for the uploads (an abort message
+    /// sent before this one arrived after the render finished, and is ignored)
+    UploadReady,
 }
 
 /// A message sent from the worker to the server
@@ -15,8 +35,67 @@ pub(crate) enum ServerMessage {
 pub(crate) enum WorkerMessage {
     /// Connected and ready to render
     Init { name: Option<String> },
-    /// Render task finished with result
+    /// Still rendering
+    Heartbeat,
+    /// Render progress update (also counts as a heartbeat)
+    Progress(RenderProgress),
+    /// Render task finished with the result of each frame (once the server is ready, followed by
+    /// an upload of the output of each frame that succeeded, in order, and then the log)
     RenderResult(RenderTaskResult),
+    /// Render task was stopped after an abort message
+    RenderAborted,
+}
+
+/// A message sent from the client to the server
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) enum ClientMessage {
+    /// Submit a new project (followed by an upload of the project file)
+    SubmitProject(Box<ProjectSettings>),
+    /// List all of the projects
+    ListProjects,
+    /// Get the status of a project (by UUID, UUID prefix, or name)
+    ProjectStatus { project: String },
+    /// Retry a project's failed frames
+    RetryFailed { project: String },
+    /// Pause a project
+    PauseProject { project: String },
+    /// Resume a paused project
+    ResumeProject { project: String },
+    /// Cancel a project
+    CancelProject { project: String },
+    /// Cancel a project and delete its files
+    RemoveProject { project: String },
+    /// Download the output of a completed frame
+    FetchOutput { project: String, frame: Frame },
+    /// Download the render log of the last attempt at rendering a frame
+    FetchLog { project: String, frame: Frame },
+    /// Download the video encoded from the frames of a project
+    FetchVideo { project: String },
+    /// List all of the workers that have connected
+    ListWorkers,
+    /// Let a quarantined worker receive tasks again
+    ReleaseWorker { worker: String },
+}
+
+/// A message sent from the server to the client
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) enum ClientResponse {
+    /// The server is ready to receive the project file
+    UploadReady,
+    /// The project was added to the scheduler
+    ProjectAdded(Uuid),
+    /// The list of projects
+    Projects(Vec<ProjectStatus>),
+    /// The status of a single project
+    Project(Box<ProjectStatus>),
+    /// The list of workers
+    Workers(Vec<WorkerStatus>),
+    /// The request was completed
+    Done,
+    /// The server is ready to send the requested file
+    OutputReady,
+    /// The request could not be completed
+    Error(String),
 }
 
 /// A message sent during file transfer
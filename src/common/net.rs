@@ -10,6 +10,18 @@ pub(crate) fn read_json<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::R
     Ok(serde_json::from_str(&buffer)?)
 }
 
+/// Read and deserialize a newline-delimited JSON object, keeping any partial line in the buffer
+/// if the read fails so that it can be resumed (e.g. after a timeout)
+pub(crate) fn read_json_resumable<T: DeserializeOwned>(
+    reader: &mut impl BufRead,
+    buffer: &mut Vec<u8>,
+) -> io::Result<T> {
+    reader.read_until(b'\n', buffer)?;
+    let result = serde_json::from_slice(buffer);
+    buffer.clear();
+    Ok(result?)
+}
+
 /// Serialize and write a newline-delimited JSON object
 pub(crate) fn write_json(writer: &mut impl Write, object: impl Serialize) -> io::Result<()> {
     let serialized = serde_json::to_string(&object)?;
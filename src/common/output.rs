@@ -0,0 +1,132 @@
+use crate::common::render_task::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Format-specific settings of the output files, which replace the ones saved in the project file
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub(crate) struct OutputOptions {
+    /// The number of bits per color channel (e.g. 16 for half float or 32 for full float EXR)
+    pub color_depth: Option<u32>,
+    /// The compression of OpenEXR files
+    pub exr_codec: Option<ExrCodec>,
+    /// The quality of lossy formats, from 0 to 100
+    pub quality: Option<u32>,
+}
+
+/// A compression codec for OpenEXR files
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
+pub(crate) enum ExrCodec {
+    None,
+    Pxr24,
+    Zip,
+    Piz,
+    Rle,
+    Zips,
+    B44,
+    B44a,
+    Dwaa,
+    Dwab,
+}
+
+/// The highest quality of lossy formats
+const MAX_QUALITY: u32 = 100;
+
+impl OutputOptions {
+    /// Check that the options are valid for the output format, returning a description of the
+    /// problem if not
+    pub(crate) fn validate(&self, output_ext: FileExt) -> Result<(), String> {
+        if let Some(color_depth) = self.color_depth {
+            let color_depths = output_ext.color_depths();
+            if color_depths.is_empty() {
+                return Err(format!("the color depth of {} files cannot be set", output_ext));
+            }
+            if !color_depths.contains(&color_depth) {
+                let color_depths: Vec<_> =
+                    color_depths.iter().map(|depth| depth.to_string()).collect();
+                return Err(format!(
+                    "the color depth of {} files must be one of {}",
+                    output_ext,
+                    color_depths.join(", ")
+                ));
+            }
+        }
+        if self.exr_codec.is_some() && !output_ext.is_exr() {
+            return Err(String::from("the EXR codec can only be set for EXR files"));
+        }
+        if let Some(quality) = self.quality {
+            if !output_ext.has_quality() {
+                return Err(format!("the quality of {} files cannot be set", output_ext));
+            }
+            if quality > MAX_QUALITY {
+                return Err(format!("the quality must be at most {}", MAX_QUALITY));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether no options are set
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == OutputOptions::default()
+    }
+}
+
+impl ExrCodec {
+    /// Get the name Blender uses for the codec
+    pub(crate) fn blender_codec(self) -> &'static str {
+        match self {
+            Self::None => "NONE",
+            Self::Pxr24 => "PXR24",
+            Self::Zip => "ZIP",
+            Self::Piz => "PIZ",
+            Self::Rle => "RLE",
+            Self::Zips => "ZIPS",
+            Self::B44 => "B44",
+            Self::B44a => "B44A",
+            Self::Dwaa => "DWAA",
+            Self::Dwab => "DWAB",
+        }
+    }
+}
+
+impl fmt::Display for OutputOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut options = Vec::new();
+        if let Some(color_depth) = self.color_depth {
+            options.push(format!("{}-bit", color_depth));
+        }
+        if let Some(exr_codec) = self.exr_codec {
+            options.push(format!("{} compression", exr_codec));
+        }
+        if let Some(quality) = self.quality {
+            options.push(format!("{}% quality", quality));
+        }
+        write!(f, "{}", options.join(", "))
+    }
+}
+
+impl fmt::Display for ExrCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.blender_codec().to_lowercase())
+    }
+}
+
+impl FromStr for ExrCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "pxr24" => Ok(Self::Pxr24),
+            "zip" => Ok(Self::Zip),
+            "piz" => Ok(Self::Piz),
+            "rle" => Ok(Self::Rle),
+            "zips" => Ok(Self::Zips),
+            "b44" => Ok(Self::B44),
+            "b44a" => Ok(Self::B44a),
+            "dwaa" => Ok(Self::Dwaa),
+            "dwab" => Ok(Self::Dwab),
+            _ => Err(format!("unknown EXR codec: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Render settings that replace the ones saved in the project file
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub(crate) struct RenderOverrides {
+    /// The scene to render instead of the active scene
+    pub scene: Option<String>,
+    /// The only view layer to render, instead of every view layer enabled for rendering
+    pub view_layer: Option<String>,
+    /// The name of the camera object to render with
+    pub camera: Option<String>,
+    pub resolution_x: Option<u32>,
+    pub resolution_y: Option<u32>,
+    /// The percentage of the resolution to render at
+    pub resolution_percentage: Option<u32>,
+    /// The number of samples per pixel
+    pub samples: Option<u32>,
+    pub engine: Option<RenderEngine>,
+    /// Whether to denoise the render (Cycles only)
+    pub denoise: Option<bool>,
+}
+
+/// A render engine that can be selected with an override
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
+pub(crate) enum RenderEngine {
+    Cycles,
+    Eevee,
+    Workbench,
+}
+
+/// The smallest resolution Blender can render
+const MIN_RESOLUTION: u32 = 4;
+
+impl RenderOverrides {
+    /// Check that the overrides are valid, returning a description of the problem if not
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.scene.as_deref() == Some("") {
+            return Err(String::from("the scene name must not be empty"));
+        }
+        if self.view_layer.as_deref() == Some("") {
+            return Err(String::from("the view layer name must not be empty"));
+        }
+        if self.camera.as_deref() == Some("") {
+            return Err(String::from("the camera name must not be empty"));
+        }
+        for resolution in self.resolution_x.iter().chain(&self.resolution_y) {
+            if *resolution < MIN_RESOLUTION {
+                return Err(format!("the resolution must be at least {}", MIN_RESOLUTION));
+            }
+        }
+        if self.resolution_percentage == Some(0) {
+            return Err(String::from("the resolution percentage must be at least 1"));
+        }
+        if self.samples == Some(0) {
+            return Err(String::from("the number of samples must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Check whether no settings are overridden
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == RenderOverrides::default()
+    }
+}
+
+impl fmt::Display for RenderOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut overrides = Vec::new();
+        if let Some(scene) = &self.scene {
+            overrides.push(format!("scene \"{}\"", scene));
+        }
+        if let Some(view_layer) = &self.view_layer {
+            overrides.push(format!("view layer \"{}\"", view_layer));
+        }
+        if let Some(camera) = &self.camera {
+            overrides.push(format!("camera \"{}\"", camera));
+        }
+        match (self.resolution_x, self.resolution_y) {
+            (Some(x), Some(y)) => overrides.push(format!("resolution {}x{}", x, y)),
+            (Some(x), None) => overrides.push(format!("resolution x {}", x)),
+            (None, Some(y)) => overrides.push(format!("resolution y {}", y)),
+            (None, None) => (),
+        }
+        if let Some(percentage) = self.resolution_percentage {
+            overrides.push(format!("{}% resolution", percentage));
+        }
+        if let Some(samples) = self.samples {
+            overrides.push(format!("{} samples", samples));
+        }
+        if let Some(engine) = self.engine {
+            overrides.push(format!("{} engine", engine));
+        }
+        if let Some(denoise) = self.denoise {
+            overrides.push(String::from(if denoise { "denoised" } else { "not denoised" }));
+        }
+        write!(f, "{}", overrides.join(", "))
+    }
+}
+
+impl fmt::Display for RenderEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Cycles => write!(f, "cycles"),
+            Self::Eevee => write!(f, "eevee"),
+            Self::Workbench => write!(f, "workbench"),
+        }
+    }
+}
+
+impl FromStr for RenderEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cycles" => Ok(Self::Cycles),
+            "eevee" => Ok(Self::Eevee),
+            "workbench" => Ok(Self::Workbench),
+            _ => Err(format!("unknown render engine: {}", s)),
+        }
+    }
+}
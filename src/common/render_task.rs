@@ -1,41 +1,207 @@
+use crate::common::output::OutputOptions;
+use crate::common::overrides::RenderOverrides;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
 use uuid::Uuid;
 
 pub(crate) type Frame = u32;
 
-/// Information about a frame that should be rendered and the project it belongs to
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Information about a chunk of consecutive frames that should be rendered and the project it
+/// belongs to
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub(crate) struct RenderTask {
     pub project_uuid: Uuid,
     pub project_name: String,
-    pub frame: Frame,
+    /// The project whose project file is rendered (projects can share the same upload)
+    pub file_uuid: Uuid,
+    pub start_frame: Frame,
+    pub end_frame: Frame,
     pub output_ext: FileExt,
+    pub output_options: OutputOptions,
+    pub overrides: RenderOverrides,
+}
+
+/// The result of rendering a single frame
+pub(crate) type FrameResult = Result<(), RenderFailure>;
+
+/// The result of a render task, with the result of each of its frames
+pub(crate) type RenderTaskResult = BTreeMap<Frame, FrameResult>;
+
+/// The progress of a render task, as reported by the render process
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub(crate) struct RenderProgress {
+    /// The frame being rendered
+    pub frame: Option<Frame>,
+    /// The fraction of the frame that has been rendered
+    pub fraction: Option<f32>,
+    /// The time the render process estimates is left
+    pub remaining: Option<Duration>,
+    /// The peak memory usage reported by the render process
+    pub peak_memory: Option<String>,
+}
+
+/// Details about why a render task failed
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct RenderFailure {
+    pub kind: RenderFailureKind,
+    /// A description of the error
+    pub message: String,
+    /// The exit code of the render process, if it exited normally
+    pub exit_code: Option<i32>,
+    /// The signal that terminated the render process, if any
+    pub signal: Option<i32>,
+    /// The last lines written to stderr by the render process
+    pub stderr_tail: Vec<String>,
 }
 
-/// The result of a render task
-pub(crate) type RenderTaskResult = Result<(), ()>;
+/// The kind of error that caused a render task to fail
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
+pub(crate) enum RenderFailureKind {
+    /// The render process could not be started
+    ExecFailed,
+    /// The render process exited with an error
+    ExitStatus,
+    /// The render process exited without writing the output file
+    OutputMissing,
+    /// The worker stopped responding while rendering
+    LeaseExpired,
+}
 
-/// The extension of an output file of a render task
-#[derive(Debug, Deserialize, Serialize, Copy, Clone)]
+/// The format of the output files of a render task
+// The variants are named after the file formats (and serialized with those names)
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
 pub(crate) enum FileExt {
-    BMP, // BMP
-    RGB, // Iris
-    PNG, // PNG
-    JPG, // JPEG
-    JP2, // JPEG 2000
-    TGA, // Targa/Targa Raw
+    BMP,           // BMP
+    RGB,           // Iris
+    PNG,           // PNG
+    JPG,           // JPEG
+    JP2,           // JPEG 2000
+    TGA,           // Targa/Targa Raw
+    EXR,           // OpenEXR
+    MultilayerEXR, // OpenEXR with every render pass in a separate layer
+    HDR,           // Radiance HDR
+    TIF,           // TIFF
+    WEBP,          // WebP
+}
+
+impl RenderTask {
+    /// Get the frames covered by the task
+    pub(crate) fn frames(&self) -> RangeInclusive<Frame> {
+        self.start_frame..=self.end_frame
+    }
+
+    /// Get the number of frames covered by the task
+    pub(crate) fn num_frames(&self) -> Frame {
+        self.end_frame - self.start_frame + 1
+    }
+}
+
+impl fmt::Display for RenderTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start_frame == self.end_frame {
+            write!(f, "project {} frame {}", self.project_uuid, self.start_frame)
+        } else {
+            write!(
+                f,
+                "project {} frames {}-{}",
+                self.project_uuid, self.start_frame, self.end_frame
+            )
+        }
+    }
+}
+
+impl fmt::Display for RenderFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl FileExt {
+    /// Get the extension of the output files
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::BMP => "bmp",
+            Self::RGB => "rgb",
+            Self::PNG => "png",
+            Self::JPG => "jpg",
+            Self::JP2 => "jp2",
+            Self::TGA => "tga",
+            Self::EXR | Self::MultilayerEXR => "exr",
+            Self::HDR => "hdr",
+            Self::TIF => "tif",
+            Self::WEBP => "webp",
+        }
+    }
+
+    /// Get the name Blender uses for the format
+    pub(crate) fn blender_format(self) -> &'static str {
+        match self {
+            Self::BMP => "BMP",
+            Self::RGB => "IRIS",
+            Self::PNG => "PNG",
+            Self::JPG => "JPEG",
+            Self::JP2 => "JP2",
+            Self::TGA => "TGA",
+            Self::EXR => "OPEN_EXR",
+            Self::MultilayerEXR => "OPEN_EXR_MULTILAYER",
+            Self::HDR => "HDR",
+            Self::TIF => "TIFF",
+            Self::WEBP => "WEBP",
+        }
+    }
+
+    /// Get the bits per channel the format supports, if it supports more than one
+    pub(crate) fn color_depths(self) -> &'static [u32] {
+        match self {
+            Self::RGB | Self::PNG | Self::TIF => &[8, 16],
+            Self::JP2 => &[8, 12, 16],
+            Self::EXR | Self::MultilayerEXR => &[16, 32],
+            Self::BMP | Self::JPG | Self::TGA | Self::HDR | Self::WEBP => &[],
+        }
+    }
+
+    /// Check whether the format is OpenEXR
+    pub(crate) fn is_exr(self) -> bool {
+        matches!(self, Self::EXR | Self::MultilayerEXR)
+    }
+
+    /// Check whether the format is lossy and has a quality setting
+    pub(crate) fn has_quality(self) -> bool {
+        matches!(self, Self::JPG | Self::JP2 | Self::WEBP)
+    }
 }
 
 impl fmt::Display for FileExt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::BMP => write!(f, "bmp"),
-            Self::RGB => write!(f, "rgb"),
-            Self::PNG => write!(f, "png"),
-            Self::JPG => write!(f, "jpg"),
-            Self::JP2 => write!(f, "jp2"),
-            Self::TGA => write!(f, "tga"),
+            Self::MultilayerEXR => write!(f, "multilayer-exr"),
+            _ => write!(f, "{}", self.extension()),
+        }
+    }
+}
+
+impl FromStr for FileExt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bmp" => Ok(Self::BMP),
+            "rgb" => Ok(Self::RGB),
+            "png" => Ok(Self::PNG),
+            "jpg" | "jpeg" => Ok(Self::JPG),
+            "jp2" => Ok(Self::JP2),
+            "tga" => Ok(Self::TGA),
+            "exr" => Ok(Self::EXR),
+            "multilayer-exr" => Ok(Self::MultilayerEXR),
+            "hdr" => Ok(Self::HDR),
+            "tif" | "tiff" => Ok(Self::TIF),
+            "webp" => Ok(Self::WEBP),
+            _ => Err(format!("unsupported format: {}", s)),
         }
     }
 }
@@ -0,0 +1,30 @@
+use crate::common::render_task::RenderFailure;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// How the scheduler retries frames that failed to render
+#[derive(Debug, Deserialize, Serialize, Copy, Clone)]
+pub(crate) struct RetryPolicy {
+    /// The maximum number of times a frame is rendered before it is marked as failed
+    pub max_attempts: u32,
+    /// How long to wait before rendering a failed frame again
+    pub retry_delay: Duration,
+}
+
+/// Projects saved before retries were added marked frames as failed after a single attempt
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 1, retry_delay: Duration::default() }
+    }
+}
+
+/// A failed attempt at rendering a frame
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct FrameAttempt {
+    /// The worker that rendered the frame
+    pub worker: Option<String>,
+    /// When the attempt failed
+    pub time: SystemTime,
+    /// Why the attempt failed (missing for attempts recorded by older versions)
+    pub failure: Option<RenderFailure>,
+}
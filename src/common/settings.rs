@@ -0,0 +1,117 @@
+use crate::common::frame_spec::FrameSpec;
+use crate::common::output::OutputOptions;
+use crate::common::overrides::RenderOverrides;
+use crate::common::render_task::{FileExt, Frame};
+use crate::common::retry::RetryPolicy;
+use crate::common::video::VideoSettings;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The lowest priority a project can have
+pub(crate) const MIN_PRIORITY: i32 = -10;
+/// The highest priority a project can have
+pub(crate) const MAX_PRIORITY: i32 = 10;
+
+/// The settings of a project, as submitted by a client
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct ProjectSettings {
+    pub name: String,
+    pub output_ext: FileExt,
+    /// Format-specific settings of the output files
+    pub output_options: OutputOptions,
+    /// The frames to render, as a frame specification (e.g. "1-100x5,120,130-140")
+    pub frames: String,
+    pub retry_policy: RetryPolicy,
+    /// Projects with a higher priority are rendered first
+    pub priority: i32,
+    /// The share of the workers a project gets relative to other projects of the same priority
+    pub weight: u32,
+    /// The maximum number of consecutive frames rendered by a single task (frames separated by a
+    /// gap or a step are never rendered by the same task)
+    pub chunk_size: Frame,
+    /// The order in which the frames are rendered
+    pub frame_order: FrameOrder,
+    /// Render settings that replace the ones saved in the project file
+    pub overrides: RenderOverrides,
+    /// A project (UUID, UUID prefix or name) whose project file is rendered instead of uploading
+    /// a new one
+    pub shared_file: Option<String>,
+    /// The video to encode the frames into once every frame has been rendered
+    pub video: Option<VideoSettings>,
+}
+
+/// The order in which the frames of a project are rendered
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Default)]
+pub(crate) enum FrameOrder {
+    /// From the first frame to the last
+    #[default]
+    Sequential,
+    /// Coarse to fine (the first and last frames, then the middle, then the quarters and so on),
+    /// so that a rough version of the whole sequence is available early
+    Progressive,
+}
+
+impl ProjectSettings {
+    /// Check that the settings are valid, returning a description of the problem if not
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        // The name defaults to the name of the project whose file is shared
+        if self.name.is_empty() && self.shared_file.is_none() {
+            return Err(String::from("the project name must not be empty"));
+        }
+        self.output_options.validate(self.output_ext)?;
+        let frame_spec = self.frame_spec()?;
+        if self.retry_policy.max_attempts == 0 {
+            return Err(String::from("the maximum number of attempts must be at least 1"));
+        }
+        if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&self.priority) {
+            return Err(format!(
+                "the priority must be between {} and {}",
+                MIN_PRIORITY, MAX_PRIORITY
+            ));
+        }
+        if self.weight == 0 {
+            return Err(String::from("the weight must be at least 1"));
+        }
+        if self.chunk_size == 0 {
+            return Err(String::from("the chunk size must be at least 1"));
+        }
+        // A chunk is rendered as a single range of frames, which cannot skip frames
+        if self.chunk_size > 1 && frame_spec.has_steps() {
+            return Err(String::from("frames with a step must be rendered with a chunk size of 1"));
+        }
+        self.overrides.validate()?;
+        if let Some(video) = &self.video {
+            video.validate(self.output_ext)?;
+        }
+        Ok(())
+    }
+
+    /// Parse the frame specification, returning a description of the problem if it is invalid
+    pub(crate) fn frame_spec(&self) -> Result<FrameSpec, String> {
+        self.frames
+            .parse()
+            .map_err(|error| format!("invalid frames \"{}\": {}", self.frames, error))
+    }
+}
+
+impl fmt::Display for FrameOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sequential => write!(f, "sequential"),
+            Self::Progressive => write!(f, "progressive"),
+        }
+    }
+}
+
+impl FromStr for FrameOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sequential" => Ok(Self::Sequential),
+            "progressive" => Ok(Self::Progressive),
+            _ => Err(format!("unknown frame order: {}", s)),
+        }
+    }
+}
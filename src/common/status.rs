@@ -0,0 +1,89 @@
+use crate::common::frame_spec::FrameSpec;
+use crate::common::output::OutputOptions;
+use crate::common::overrides::RenderOverrides;
+use crate::common::render_task::{FileExt, Frame, RenderProgress};
+use crate::common::retry::{FrameAttempt, RetryPolicy};
+use crate::common::settings::FrameOrder;
+use crate::common::video::{VideoSettings, VideoState};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A snapshot of the state of a project, as reported by the scheduler
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct ProjectStatus {
+    pub uuid: Uuid,
+    pub name: String,
+    pub output_ext: FileExt,
+    pub output_options: OutputOptions,
+    /// The frames the project was submitted with
+    pub frame_spec: FrameSpec,
+    pub frame_order: FrameOrder,
+    pub overrides: RenderOverrides,
+    /// The project whose project file is rendered, if it is not the project's own upload
+    pub shared_file: Option<Uuid>,
+    pub video: Option<VideoSettings>,
+    pub video_state: Option<VideoState>,
+    pub cancelled: bool,
+    pub paused: bool,
+    pub priority: i32,
+    pub weight: u32,
+    pub chunk_size: Frame,
+    pub num_waiting: Frame,
+    pub num_assigned: Frame,
+    pub num_completed: Frame,
+    pub num_failed: Frame,
+    pub progress: f32,
+    pub waiting_frames: Vec<Frame>,
+    pub assigned_frames: Vec<AssignedFrame>,
+    pub completed_frames: Vec<Frame>,
+    pub failed_frames: Vec<Frame>,
+    /// The failed attempts at rendering each frame
+    pub attempts: BTreeMap<Frame, Vec<FrameAttempt>>,
+    pub retry_policy: RetryPolicy,
+    /// The estimated time until the remaining frames are rendered, if it can be estimated
+    pub eta: Option<Duration>,
+}
+
+/// A frame that has been assigned to a worker
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct AssignedFrame {
+    pub frame: Frame,
+    /// The worker rendering the frame, or `None` if no worker has picked it up yet
+    pub worker: Option<String>,
+    /// The progress of the render, or `None` if it has not started
+    pub progress: Option<RenderProgress>,
+}
+
+/// A snapshot of the state of a worker, as reported by the scheduler
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct WorkerStatus {
+    pub name: String,
+    /// The number of open connections from the worker
+    pub connections: usize,
+    pub num_completed: u32,
+    pub num_failed: u32,
+    /// The number of frames the worker has failed since its last successful render
+    pub consecutive_failures: u32,
+    /// Whether the worker has stopped receiving tasks because it failed too many frames in a row
+    pub quarantined: bool,
+}
+
+impl ProjectStatus {
+    /// Get the total number of frames
+    pub(crate) fn num_frames(&self) -> Frame {
+        self.num_waiting + self.num_assigned + self.num_completed + self.num_failed
+    }
+
+    /// Get a label for the state of the project if it is not active
+    pub(crate) fn state_label(&self) -> Option<&'static str> {
+        if self.cancelled {
+            Some("cancelled")
+        } else if self.paused {
+            Some("paused")
+        } else {
+            None
+        }
+    }
+}
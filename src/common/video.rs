@@ -0,0 +1,105 @@
+use crate::common::render_task::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The highest frame rate a video can have
+const MAX_FRAME_RATE: f64 = 1000.0;
+
+/// How the frames of a project are encoded into a video once every frame has been rendered
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub(crate) struct VideoSettings {
+    pub codec: VideoCodec,
+    /// The number of frames per second
+    pub frame_rate: f64,
+}
+
+/// A video codec, along with the container it is stored in
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
+pub(crate) enum VideoCodec {
+    /// H.264 in an MP4 file
+    H264,
+    /// Apple ProRes 422 HQ in a QuickTime file
+    ProRes,
+    /// An animated GIF, for previews
+    Gif,
+}
+
+/// The state of the video of a project
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub(crate) enum VideoState {
+    /// The frames are being encoded
+    Encoding,
+    /// The video is ready to be downloaded
+    Finished,
+    /// The frames could not be encoded, with a description of the error
+    Failed(String),
+}
+
+impl VideoSettings {
+    /// Check that the settings are valid for the output format, returning a description of the
+    /// problem if not
+    pub(crate) fn validate(&self, output_ext: FileExt) -> Result<(), String> {
+        if !(self.frame_rate > 0.0 && self.frame_rate <= MAX_FRAME_RATE) {
+            return Err(format!(
+                "the frame rate must be greater than 0 and at most {}",
+                MAX_FRAME_RATE
+            ));
+        }
+        // The layers of multilayer EXR files are not understood by ffmpeg
+        if output_ext == FileExt::MultilayerEXR {
+            return Err(format!("a video cannot be encoded from {} files", output_ext));
+        }
+        Ok(())
+    }
+}
+
+impl VideoCodec {
+    /// Get the extension of the video file
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::H264 => "mp4",
+            Self::ProRes => "mov",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+impl fmt::Display for VideoSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {} fps", self.codec, self.frame_rate)
+    }
+}
+
+impl fmt::Display for VideoCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::H264 => write!(f, "h264"),
+            Self::ProRes => write!(f, "prores"),
+            Self::Gif => write!(f, "gif"),
+        }
+    }
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "h264" | "mp4" => Ok(Self::H264),
+            "prores" | "mov" => Ok(Self::ProRes),
+            "gif" => Ok(Self::Gif),
+            _ => Err(format!("unknown video codec: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for VideoState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Encoding => write!(f, "encoding"),
+            Self::Finished => write!(f, "finished"),
+            Self::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
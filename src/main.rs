@@ -1,12 +1,14 @@
 #![allow(dead_code)] // TODO: for prototyping
 
+mod client;
 mod common;
 mod server;
 mod worker;
 
+use crate::client::args::ClientArgs;
 use crate::server::args::ServerArgs;
 use crate::worker::args::WorkerArgs;
-use crate::{server::Server, worker::Worker};
+use crate::{client::Client, server::Server, worker::Worker};
 use failure::Error;
 use log::{error, LevelFilter};
 use std::process::exit;
@@ -22,10 +24,12 @@ struct Args {
     command: Command,
 }
 
+// The arguments are only parsed once, so the size of the variants does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt)]
 enum Command {
     /// Joins a server as a client
-    Client,
+    Client(ClientArgs),
     /// Hosts a server
     Server(ServerArgs),
     /// Joins a server as a worker
@@ -41,7 +45,7 @@ fn main() {
 
     // Run the client, server, or worker and check the result
     let result: Result<(), Error> = match args.command {
-        Command::Client => unimplemented!(),
+        Command::Client(args) => Client::run(args).map_err(|e| e.into()),
         Command::Server(args) => Server::run(args).map_err(|e| e.into()),
         Command::Worker(args) => Worker::run(args).map_err(|e| e.into()),
     };
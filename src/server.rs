@@ -1,19 +1,34 @@
+mod api;
 pub(super) mod args;
+mod client_connection;
 mod connection;
+mod events;
+pub(super) mod policy;
 mod project;
 mod scheduler;
+mod state;
+mod video;
+mod webhook;
 
-use crate::common::file::{get_project_file, init_working_dir};
-use crate::common::render_task::FileExt;
+use crate::common::file::{get_webhook_log_file, init_working_dir, open_data_dir};
+use crate::common::message::PeerMessage;
+use crate::common::net::read_json;
 use crate::server::args::ServerArgs;
+use crate::server::client_connection::ClientConnection;
 use crate::server::connection::Connection;
-use crate::server::project::Project;
-use crate::server::scheduler::{Scheduler, SchedulerManageMessage};
-use crossbeam_channel::Sender;
+use crate::server::events::{Hooks, Webhooks};
+use crate::server::scheduler::{
+    Scheduler, SchedulerManageMessage, SchedulerRenderMessage, SchedulerResultMessage,
+};
+use crate::server::state::load_projects;
+use crossbeam_channel::{Receiver, Sender};
 use failure::Fail;
-use log::{debug, info};
-use std::net::TcpListener;
+use log::{debug, error, info};
+use std::fs::OpenOptions;
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
 use std::path::Path;
+use std::time::Duration;
 use std::{io, thread};
 
 pub(super) type ServerResult<T> = Result<T, ServerError>;
@@ -24,6 +39,8 @@ pub(super) enum ServerError {
     WorkingDirError(#[fail(cause)] io::Error),
     #[fail(display = "Error starting server: {}", 0)]
     InitError(#[fail(cause)] io::Error),
+    #[fail(display = "Error loading saved state: {}", 0)]
+    StateLoadFailed(#[fail(cause)] io::Error),
 }
 
 pub(super) struct Server {}
@@ -33,53 +50,125 @@ impl Server {
     pub(super) fn run(args: ServerArgs) -> ServerResult<()> {
         info!("Starting server on {}:{}...", args.address, args.port);
 
-        // Initialize the working directory
-        let working_dir = init_working_dir("server").map_err(ServerError::WorkingDirError)?;
+        // Initialize the working directory, using the data directory if one was specified
+        let working_dir = match &args.data_dir {
+            Some(data_dir) => open_data_dir(data_dir),
+            None => init_working_dir("server"),
+        }
+        .map_err(ServerError::WorkingDirError)?;
+
+        // Load the projects saved by a previous run
+        let projects = load_projects(&working_dir).map_err(ServerError::StateLoadFailed)?;
 
         // Bind to the socket
         debug!("Binding to socket...");
         let listener = TcpListener::bind((args.address.as_str(), args.port))
             .map_err(ServerError::InitError)?;
 
+        // Open the webhook delivery log, keeping the records of previous runs
+        let delivery_log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(get_webhook_log_file(&working_dir))
+            .map_err(ServerError::InitError)?;
+
+        // Start handling events in a new thread
+        let hooks = Hooks {
+            frame_completed: args.on_frame_completed,
+            frame_failed: args.on_frame_failed,
+            project_completed: args.on_project_completed,
+            timeout: Duration::from_secs(args.hook_timeout),
+        };
+        let webhooks = Webhooks {
+            urls: args.webhooks,
+            max_attempts: args.webhook_attempts.max(1),
+            delivery_log,
+        };
+        let event_send = events::start(hooks, webhooks);
+
         // Start the scheduler in a new thread
         debug!("Starting scheduler...");
-        let (render_recv, result_send, manage_send) = Scheduler::start();
+        let (render_recv, result_send, manage_send) = Scheduler::start(
+            projects,
+            working_dir.clone(),
+            args.quarantine_after,
+            args.policy.create(),
+            event_send,
+        );
+
+        // Start the HTTP/JSON API in a new thread, if it is enabled
+        if let Some(api_port) = args.api_port {
+            debug!("Binding to API socket...");
+            let api_listener = TcpListener::bind((args.address.as_str(), api_port))
+                .map_err(ServerError::InitError)?;
+            api::start(api_listener, manage_send.clone(), working_dir.clone());
+            info!("Serving the API on {}:{}", args.address, api_port);
+        }
 
         info!("Server started!");
 
-        // Add some test projects
-        for index in 1..=4 {
-            let project = Project::new(format!("Project {}", index), FileExt::PNG, 1, index * 2);
-            Self::add_dummy_project(project, &working_dir, &manage_send);
-        }
+        let lease = Duration::from_secs(args.worker_timeout);
 
         // Handle incoming connections
         for stream in listener.incoming().filter_map(|stream| stream.ok()) {
-            // Clone render and result channel endpoints
+            // Clone channel endpoints
             let render_recv = render_recv.clone();
             let result_send = result_send.clone();
+            let manage_send = manage_send.clone();
             let working_dir = working_dir.clone();
             // Spawn a thread to handle the connection
             thread::spawn(move || {
-                Connection::handle(stream, render_recv, result_send, &working_dir)
+                Self::handle_connection(
+                    stream,
+                    render_recv,
+                    result_send,
+                    manage_send,
+                    &working_dir,
+                    lease,
+                )
             });
         }
         unreachable!();
     }
 
-    // Send a project to the scheduler, copying the project file from /tmp/untitled.blend
-    fn add_dummy_project(
-        project: Project,
+    /// Handle an incoming connection as either a worker or a client, based on the first message
+    fn handle_connection(
+        stream: TcpStream,
+        render_recv: Receiver<SchedulerRenderMessage>,
+        result_send: Sender<SchedulerResultMessage>,
+        manage_send: Sender<SchedulerManageMessage>,
         working_dir: &Path,
-        manage_send: &Sender<SchedulerManageMessage>,
+        lease: Duration,
     ) {
-        // Copy the project file from /tmp/untitled.blend
-        let project_file = get_project_file(working_dir, &project.uuid);
-        std::fs::create_dir(project_file.parent().unwrap())
-            .expect("unable to create project directory");
-        std::fs::copy("/tmp/untitled.blend", project_file)
-            .expect("unable to copy dummy project file");
-        // Send the project to the scheduler
-        manage_send.send(SchedulerManageMessage::AddProject(project)).unwrap();
+        let addr = match stream.peer_addr() {
+            Ok(addr) => addr.ip(),
+            Err(_) => return,
+        };
+        let mut reader = BufReader::new(&stream);
+        let writer = BufWriter::new(&stream);
+
+        debug!("Incoming connection from {}", &addr);
+
+        // Read the first message and hand the connection off to the matching handler
+        match read_json(&mut reader) {
+            Ok(PeerMessage::Worker(message)) => {
+                debug!("{} -> {:?}", &addr, &message);
+                Connection::handle(
+                    addr,
+                    reader,
+                    writer,
+                    message,
+                    render_recv,
+                    result_send,
+                    working_dir,
+                    lease,
+                )
+            }
+            Ok(PeerMessage::Client(message)) => {
+                debug!("{} -> {:?}", &addr, &message);
+                ClientConnection::handle(addr, reader, writer, message, manage_send, working_dir)
+            }
+            Err(error) => error!("Invalid first message from {}: {}", &addr, error),
+        }
     }
 }
@@ -0,0 +1,685 @@
+use crate::common::file::{
+    get_frame_file, get_frame_file_name, get_frame_log_file, get_video_file, get_video_file_name,
+};
+use crate::common::render_task::Frame;
+use crate::common::settings::ProjectSettings;
+use crate::common::status::ProjectStatus;
+use crate::common::video::VideoState;
+use crate::server::client_connection::{new_project, store_project};
+use crate::server::scheduler::{find_project, list_projects, list_workers, SchedulerManageMessage};
+use crossbeam_channel::Sender;
+use failure::Fail;
+use log::{debug, error, info};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long to wait for a client to send a request
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// The largest request head (request line and headers) that is accepted
+const MAX_HEAD_SIZE: u64 = 64 * 1024;
+/// The largest JSON body that is accepted
+const MAX_JSON_SIZE: u64 = 1024 * 1024;
+
+type ApiResult<T> = Result<T, ApiError>;
+
+#[derive(Fail, Debug)]
+enum ApiError {
+    #[fail(display = "I/O error: {}", 0)]
+    IoError(#[fail(cause)] io::Error),
+    /// An error reported to the client with an HTTP status
+    #[fail(display = "{} ({})", 1, 0)]
+    Status(u16, String),
+}
+
+/// An HTTP request, without the body
+struct Request {
+    method: String,
+    /// The decoded segments of the path
+    path: Vec<String>,
+    /// The decoded query parameters
+    query: HashMap<String, String>,
+    /// The headers, with lowercase names
+    headers: HashMap<String, String>,
+}
+
+/// A connection to an API client, which sends a single request
+struct ApiConnection<'a> {
+    addr: IpAddr,
+    reader: BufReader<&'a TcpStream>,
+    writer: &'a TcpStream,
+    manage_send: Sender<SchedulerManageMessage>,
+    project_dir: &'a Path,
+    /// Whether the response has been started
+    responded: bool,
+}
+
+/// The response to a submitted project
+#[derive(Serialize)]
+struct ProjectAdded {
+    uuid: Uuid,
+}
+
+/// The body of an error response
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+/// Start accepting connections to the HTTP/JSON API in a new thread
+pub(super) fn start(
+    listener: TcpListener,
+    manage_send: Sender<SchedulerManageMessage>,
+    working_dir: PathBuf,
+) {
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+            let manage_send = manage_send.clone();
+            let working_dir = working_dir.clone();
+            thread::spawn(move || ApiConnection::handle(&stream, manage_send, &working_dir));
+        }
+    });
+}
+
+impl<'a> ApiConnection<'a> {
+    /// Handle a request from an API client
+    fn handle(
+        stream: &'a TcpStream,
+        manage_send: Sender<SchedulerManageMessage>,
+        project_dir: &'a Path,
+    ) {
+        let addr = match stream.peer_addr() {
+            Ok(addr) => addr.ip(),
+            Err(_) => return,
+        };
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let mut connection = ApiConnection {
+            addr,
+            reader: BufReader::new(stream),
+            writer: stream,
+            manage_send,
+            project_dir,
+            responded: false,
+        };
+        let result = match connection.read_request() {
+            Ok(request) => {
+                debug!("{} -> {} /{}", &addr, &request.method, request.path.join("/"));
+                connection.handle_request(&request)
+            }
+            Err(error) => Err(error),
+        };
+        // Report errors to the client if possible
+        if let Err(error) = result {
+            match error {
+                ApiError::Status(status, message) => {
+                    debug!("{} <- {} {}", &addr, status, &message);
+                    let _ = connection.write_json(status, &ErrorBody { error: &message });
+                }
+                ApiError::IoError(error) => {
+                    error!("API client {}: {}", &addr, error);
+                    // An error while writing the response cannot be reported in the response
+                    if !connection.responded {
+                        let message = error.to_string();
+                        let _ = connection.write_json(500, &ErrorBody { error: &message });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a request, writing the response
+    /// Projects are referred to by UUID, UUID prefix or name, as in the client, and errors are
+    /// reported as `{"error": "..."}` with an HTTP error status
+    fn handle_request(&mut self, request: &Request) -> ApiResult<()> {
+        let path: Vec<&str> = request.path.iter().map(String::as_str).collect();
+        match (request.method.as_str(), path.as_slice()) {
+            // List the projects
+            ("GET", ["projects"]) => {
+                let projects = list_projects(&self.manage_send);
+                self.write_json(200, &projects)
+            }
+            // Submit a project
+            ("POST", ["projects"]) => self.submit_project(request),
+            // Get the status of a project
+            ("GET", ["projects", project]) => {
+                let project = self.find_project(project)?;
+                self.write_json(200, &project)
+            }
+            // Cancel a project and delete its files
+            ("DELETE", ["projects", project]) => {
+                let project = self.find_project(project)?;
+                debug!("Removing project \"{}\" for API client {}", &project.name, &self.addr);
+                self.send_manage_message(SchedulerManageMessage::RemoveProject(project.uuid));
+                self.write_empty()
+            }
+            // Cancel, retry the failed frames of, pause or resume a project
+            ("POST", ["projects", project, action])
+                if ["cancel", "retry", "pause", "resume"].contains(action) =>
+            {
+                let project = self.find_project(project)?;
+                if ["retry", "resume"].contains(action) && project.cancelled {
+                    let error = format!("project \"{}\" has been cancelled", project.name);
+                    return Err(ApiError::Status(409, error));
+                }
+                let message = match *action {
+                    "cancel" => SchedulerManageMessage::CancelProject(project.uuid),
+                    "retry" => SchedulerManageMessage::RetryFailed(project.uuid),
+                    "pause" => SchedulerManageMessage::PauseProject(project.uuid),
+                    "resume" => SchedulerManageMessage::ResumeProject(project.uuid),
+                    _ => unreachable!(),
+                };
+                debug!(
+                    "Handling {} of project \"{}\" for API client {}",
+                    action, &project.name, &self.addr
+                );
+                self.send_manage_message(message);
+                self.write_empty()
+            }
+            // Download the output of a frame
+            ("GET", ["projects", project, "frames", frame]) => {
+                let project = self.find_project(project)?;
+                self.send_output(&project, parse_frame(frame)?)
+            }
+            // Download the render log of a frame
+            ("GET", ["projects", project, "frames", frame, "log"]) => {
+                let project = self.find_project(project)?;
+                let frame = parse_frame(frame)?;
+                let log_file = get_frame_log_file(self.project_dir, &project.uuid, frame);
+                if !log_file.is_file() {
+                    let error =
+                        format!("frame {} of \"{}\" has no render log", frame, project.name);
+                    return Err(ApiError::Status(404, error));
+                }
+                self.write_file(&log_file, "text/plain; charset=utf-8", None)
+            }
+            // Download the video of a project
+            ("GET", ["projects", project, "video"]) => {
+                let project = self.find_project(project)?;
+                self.send_video(&project)
+            }
+            // List the workers
+            ("GET", ["workers"]) => {
+                let workers = list_workers(&self.manage_send);
+                self.write_json(200, &workers)
+            }
+            // Distinguish paths that exist from paths that do not
+            (_, ["projects"])
+            | (_, ["projects", _])
+            | (_, ["projects", _, "cancel"])
+            | (_, ["projects", _, "retry"])
+            | (_, ["projects", _, "pause"])
+            | (_, ["projects", _, "resume"])
+            | (_, ["projects", _, "frames", _])
+            | (_, ["projects", _, "frames", _, "log"])
+            | (_, ["projects", _, "video"])
+            | (_, ["workers"]) => Err(ApiError::Status(405, String::from("method not allowed"))),
+            _ => Err(not_found()),
+        }
+    }
+
+    /// Submit a project from a request, either with the settings as a JSON body (for projects
+    /// that share the file of another project) or with the project file as the body and the
+    /// settings as JSON in the `settings` query parameter
+    fn submit_project(&mut self, request: &Request) -> ApiResult<()> {
+        let length = content_length(request)?;
+        let is_json = matches!(
+            request.headers.get("content-type"),
+            Some(content_type) if content_type.starts_with("application/json")
+        );
+        let settings = if is_json {
+            if length > MAX_JSON_SIZE {
+                return Err(ApiError::Status(413, String::from("request body is too large")));
+            }
+            let mut body = Vec::new();
+            (&mut self.reader).take(length).read_to_end(&mut body)?;
+            body
+        } else {
+            match request.query.get("settings") {
+                Some(settings) => settings.as_bytes().to_vec(),
+                None => {
+                    let error = "the settings must be in the body or the settings parameter";
+                    return Err(ApiError::Status(400, String::from(error)));
+                }
+            }
+        };
+        let settings: ProjectSettings = serde_json::from_slice(&settings)
+            .map_err(|error| ApiError::Status(400, format!("invalid settings: {}", error)))?;
+        let project = new_project(settings, &self.manage_send)
+            .map_err(|error| ApiError::Status(400, error))?;
+        // Receive the project file, unless the project shares the file of another project
+        let needs_upload = project.shared_file.is_none();
+        if needs_upload && (is_json || length == 0) {
+            let error = "the project file must be the body of the request";
+            return Err(ApiError::Status(400, String::from(error)));
+        }
+        let reader = &mut self.reader;
+        store_project(self.project_dir, &project, |project_file| {
+            let mut file = File::create(project_file)?;
+            if io::copy(&mut reader.take(length), &mut file)? < length {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            Ok(())
+        })?;
+        info!("Project \"{}\" submitted by API client {}", &project, &self.addr);
+        // Send the project to the scheduler
+        let project_uuid = project.uuid;
+        self.send_manage_message(SchedulerManageMessage::AddProject(Box::new(project)));
+        self.write_json(201, &ProjectAdded { uuid: project_uuid })
+    }
+
+    /// Send the output file of a completed frame
+    fn send_output(&mut self, project: &ProjectStatus, frame: Frame) -> ApiResult<()> {
+        if !project.completed_frames.contains(&frame) {
+            let error = format!("frame {} of \"{}\" has not been completed", frame, project.name);
+            return Err(ApiError::Status(404, error));
+        }
+        let output_file =
+            get_frame_file(self.project_dir, &project.uuid, frame, project.output_ext);
+        let file_name = get_frame_file_name(frame, project.output_ext);
+        self.write_file(&output_file, "application/octet-stream", Some(&file_name))
+    }
+
+    /// Send the video encoded from the frames of a project
+    fn send_video(&mut self, project: &ProjectStatus) -> ApiResult<()> {
+        let codec = match (&project.video, &project.video_state) {
+            (Some(settings), Some(VideoState::Finished)) => settings.codec,
+            _ => {
+                let error = format!("the video of \"{}\" has not been encoded", project.name);
+                return Err(ApiError::Status(404, error));
+            }
+        };
+        let video_file = get_video_file(self.project_dir, &project.uuid, codec);
+        let file_name = get_video_file_name(codec);
+        self.write_file(&video_file, "application/octet-stream", Some(&file_name))
+    }
+
+    /// Find the project a reference (a UUID, UUID prefix, or name) refers to
+    fn find_project(&self, reference: &str) -> ApiResult<ProjectStatus> {
+        find_project(&self.manage_send, reference).map_err(|error| ApiError::Status(404, error))
+    }
+
+    /// Send a management message to the scheduler
+    fn send_manage_message(&self, message: SchedulerManageMessage) {
+        self.manage_send.send(message).unwrap();
+    }
+
+    /// Read the request line and headers of a request
+    fn read_request(&mut self) -> ApiResult<Request> {
+        let mut head = (&mut self.reader).take(MAX_HEAD_SIZE);
+        let bad_request = || ApiError::Status(400, String::from("invalid request"));
+        // The request line looks like "GET /projects?key=value HTTP/1.1"
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method.to_string(), target.to_string())
+            }
+            _ => return Err(bad_request()),
+        };
+        // Read the headers until the empty line that ends them
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            if head.read_line(&mut line)? == 0 {
+                return Err(bad_request());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let index = line.find(':').ok_or_else(bad_request)?;
+            let value = line[index + 1..].trim().to_string();
+            headers.insert(line[..index].to_lowercase(), value);
+        }
+        // Split the target into the path and the query
+        let (path, query) = match target.find('?') {
+            Some(index) => (&target[..index], &target[index + 1..]),
+            None => (target.as_str(), ""),
+        };
+        let path = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect::<Option<_>>()
+            .ok_or_else(bad_request)?;
+        let query = query
+            .split('&')
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                let (key, value) = match parameter.find('=') {
+                    Some(index) => (&parameter[..index], &parameter[index + 1..]),
+                    None => (parameter, ""),
+                };
+                // Spaces can be encoded as '+' in the query
+                let (key, value) = (key.replace('+', " "), value.replace('+', " "));
+                Some((percent_decode(&key)?, percent_decode(&value)?))
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(bad_request)?;
+        Ok(Request { method, path, query, headers })
+    }
+
+    /// Write a response with a JSON body
+    fn write_json(&mut self, status: u16, body: &impl Serialize) -> ApiResult<()> {
+        let body = serde_json::to_vec(body).map_err(io::Error::from)?;
+        self.write_head(status, "application/json", body.len() as u64, None)?;
+        Ok(self.writer.write_all(&body)?)
+    }
+
+    /// Write a response without a body
+    fn write_empty(&mut self) -> ApiResult<()> {
+        self.write_head(204, "", 0, None)
+    }
+
+    /// Write a response with the contents of a file as the body
+    fn write_file(
+        &mut self,
+        path: &Path,
+        content_type: &str,
+        file_name: Option<&str>,
+    ) -> ApiResult<()> {
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len();
+        self.write_head(200, content_type, length, file_name)?;
+        io::copy(&mut file, &mut self.writer)?;
+        Ok(())
+    }
+
+    /// Write the status line and headers of a response
+    /// The connection is closed after the response, so every response has a known length
+    fn write_head(
+        &mut self,
+        status: u16,
+        content_type: &str,
+        length: u64,
+        file_name: Option<&str>,
+    ) -> ApiResult<()> {
+        self.responded = true;
+        let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason_phrase(status));
+        if !content_type.is_empty() {
+            head.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        if let Some(file_name) = file_name {
+            head.push_str(&format!(
+                "Content-Disposition: attachment; filename=\"{}\"\r\n",
+                file_name
+            ));
+        }
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", length));
+        Ok(self.writer.write_all(head.as_bytes())?)
+    }
+}
+
+/// Get the length of the body of a request
+fn content_length(request: &Request) -> ApiResult<u64> {
+    if request.headers.contains_key("transfer-encoding") {
+        let error = "chunked requests are not supported, set the Content-Length instead";
+        return Err(ApiError::Status(411, String::from(error)));
+    }
+    match request.headers.get("content-length") {
+        Some(length) => length
+            .parse()
+            .map_err(|_| ApiError::Status(400, format!("invalid Content-Length: {}", length))),
+        None => Ok(0),
+    }
+}
+
+/// Parse a frame number from a path
+fn parse_frame(frame: &str) -> ApiResult<Frame> {
+    frame.parse().map_err(|_| ApiError::Status(400, format!("invalid frame: {}", frame)))
+}
+
+/// Decode a percent-encoded path segment or query parameter
+/// Returns `None` if it is not valid percent-encoded UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Get the reason phrase for the HTTP statuses used by the API
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        _ => "Error",
+    }
+}
+
+/// Get the error for a path that does not exist
+fn not_found() -> ApiError {
+    ApiError::Status(404, String::from("not found"))
+}
+
+impl From<io::Error> for ApiError {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::file::{get_project_dir, get_project_file};
+    use crate::server::policy::PolicyKind;
+    use crate::server::project::tests::settings;
+    use crate::server::scheduler::{Scheduler, SchedulerRenderMessage};
+    use crossbeam_channel::Receiver;
+    use std::fs;
+    use std::net::{Shutdown, SocketAddr};
+
+    /// A scheduler with the API listening on a local port
+    struct TestServer {
+        addr: SocketAddr,
+        working_dir: PathBuf,
+        /// Kept so that the scheduler can queue frames
+        _render_recv: Receiver<SchedulerRenderMessage>,
+    }
+
+    impl TestServer {
+        fn start() -> TestServer {
+            let working_dir = std::env::temp_dir().join(format!("tinyrf-api-{}", Uuid::new_v4()));
+            fs::create_dir(&working_dir).unwrap();
+            let (event_send, _) = crossbeam_channel::unbounded();
+            let (render_recv, _, manage_send) = Scheduler::start(
+                Vec::new(),
+                working_dir.clone(),
+                5,
+                PolicyKind::RoundRobin.create(),
+                event_send,
+            );
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            start(listener, manage_send, working_dir.clone());
+            TestServer { addr, working_dir, _render_recv: render_recv }
+        }
+
+        /// Send a request, closing the writing half of the connection after it, and get the
+        /// status, headers and body of the response
+        fn send(&self, request: &[u8]) -> (u16, String, Vec<u8>) {
+            let mut stream = TcpStream::connect(self.addr).unwrap();
+            stream.write_all(request).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            let index = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+            let head = String::from_utf8(response[..index].to_vec()).unwrap();
+            let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+            (status, head, response[index + 4..].to_vec())
+        }
+
+        /// Send a request without a body and get the status and body of the response
+        fn request(&self, method: &str, target: &str) -> (u16, serde_json::Value) {
+            let request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, target);
+            let (status, _, body) = self.send(request.as_bytes());
+            (status, json(&body))
+        }
+
+        /// Submit a project whose settings are in the query, with a project file as the body
+        fn submit(&self, name: &str, headers: &str, body: &[u8]) -> (u16, serde_json::Value) {
+            let settings = serde_json::to_string(&ProjectSettings {
+                name: name.to_string(),
+                ..settings("1-3")
+            })
+            .unwrap();
+            let request = format!(
+                "POST /projects?settings={} HTTP/1.1\r\n{}\r\n",
+                percent_encode(&settings),
+                headers
+            );
+            let (status, _, response) = self.send(&[request.as_bytes(), body].concat());
+            (status, json(&response))
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.working_dir);
+        }
+    }
+
+    /// Parse a response body, which is null if it is empty
+    fn json(body: &[u8]) -> serde_json::Value {
+        if body.is_empty() {
+            return serde_json::Value::Null;
+        }
+        serde_json::from_slice(body).unwrap()
+    }
+
+    /// Percent-encode everything except letters and digits, with spaces encoded as '+'
+    fn percent_encode(s: &str) -> String {
+        s.bytes()
+            .map(|byte| match byte {
+                b' ' => String::from("+"),
+                byte if byte.is_ascii_alphanumeric() => (byte as char).to_string(),
+                byte => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decode_percent_encoding() {
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("%e2%9c%93").as_deref(), Some("\u{2713}"));
+        // Truncated escapes, invalid hex digits and invalid UTF-8 are rejected
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+
+    #[test]
+    fn parse_requests() {
+        let server = TestServer::start();
+        // Headers are case-insensitive, and empty path segments are ignored
+        let (status, _, body) =
+            server.send(b"GET //projects/ HTTP/1.0\r\nHOST: localhost\r\nX-Empty:\r\n\r\n");
+        assert_eq!((status, json(&body)), (200, serde_json::json!([])));
+        for request in &[
+            &b"GET /projects\r\n\r\n"[..],
+            b"GET /projects HTTP/2\r\n\r\n",
+            b"GET /projects HTTP/1.1\r\nno colon\r\n\r\n",
+            b"GET /projects HTTP/1.1\r\nHost: localhost\r\n",
+            b"GET /projects/%zz HTTP/1.1\r\n\r\n",
+            b"GET /projects?key=%FF HTTP/1.1\r\n\r\n",
+        ] {
+            let (status, _, body) = server.send(request);
+            assert_eq!(status, 400, "{:?}", String::from_utf8_lossy(request));
+            assert_eq!(json(&body)["error"], "invalid request");
+        }
+    }
+
+    #[test]
+    fn route_requests() {
+        let server = TestServer::start();
+        assert_eq!(server.request("GET", "/workers"), (200, serde_json::json!([])));
+        let (status, body) = server.request("GET", "/nothing");
+        assert_eq!((status, &body["error"]), (404, &serde_json::json!("not found")));
+        assert_eq!(server.request("PUT", "/projects").0, 405);
+        assert_eq!(server.request("GET", "/projects/missing/cancel").0, 405);
+        assert_eq!(server.request("GET", "/projects/missing").0, 404);
+        assert_eq!(server.request("POST", "/projects/missing/pause").0, 404);
+        assert_eq!(server.request("POST", "/projects/missing/restart").0, 404);
+        assert_eq!(server.request("GET", "/projects/missing/frames/1/log").0, 404);
+    }
+
+    #[test]
+    fn manage_projects() {
+        let server = TestServer::start();
+        let (status, body) = server.submit("my project", "Content-Length: 5\r\n", b"blend");
+        assert_eq!(status, 201);
+        let uuid: Uuid = body["uuid"].as_str().unwrap().parse().unwrap();
+        let project_file = get_project_file(&server.working_dir, &uuid);
+        assert_eq!(fs::read(project_file).unwrap(), b"blend");
+        // Projects can be referred to by percent-encoded name
+        let (status, body) = server.request("GET", "/projects/my%20project");
+        assert_eq!((status, &body["uuid"]), (200, &serde_json::json!(uuid)));
+        assert_eq!(server.request("GET", "/projects").1.as_array().unwrap().len(), 1);
+        assert_eq!(server.request("GET", "/projects/my%20project/frames/1").0, 404);
+        assert_eq!(server.request("GET", "/projects/my%20project/frames/x").0, 400);
+        assert_eq!(server.request("GET", "/projects/my%20project/video").0, 404);
+        assert_eq!(server.request("POST", "/projects/my%20project/pause").0, 204);
+        assert_eq!(server.request("POST", "/projects/my%20project/cancel").0, 204);
+        // A cancelled project cannot be started again
+        assert_eq!(server.request("POST", "/projects/my%20project/resume").0, 409);
+        assert_eq!(server.request("POST", "/projects/my%20project/retry").0, 409);
+        assert_eq!(server.request("DELETE", "/projects/my%20project").0, 204);
+        assert_eq!(server.request("GET", "/projects/my%20project").0, 404);
+    }
+
+    #[test]
+    fn check_content_length() {
+        let server = TestServer::start();
+        let statuses = [
+            server.submit("chunked", "Transfer-Encoding: chunked\r\n", b"").0,
+            server.submit("invalid", "Content-Length: five\r\n", b"").0,
+            server.submit("empty", "", b"").0,
+            server.submit("empty", "Content-Length: 0\r\n", b"").0,
+        ];
+        assert_eq!(statuses, [411, 400, 400, 400]);
+        // JSON bodies are limited in size
+        let request = format!(
+            "POST /projects HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            MAX_JSON_SIZE + 1
+        );
+        assert_eq!(server.send(request.as_bytes()).0, 413);
+        // A body that ends early is an error, and the project is not kept
+        let (status, body) = server.submit("short", "Content-Length: 10\r\n", b"blend");
+        assert_eq!(status, 500);
+        assert!(body["error"].as_str().unwrap().contains("end of file"));
+        assert_eq!(server.request("GET", "/projects/short").0, 404);
+        let mut entries = fs::read_dir(&server.working_dir).unwrap();
+        assert!(entries.all(|entry| !entry.unwrap().path().is_dir()));
+        // The body is read up to the Content-Length
+        let (status, body) = server.submit("long", "Content-Length: 5\r\n", b"blend and more");
+        assert_eq!(status, 201);
+        let uuid: Uuid = body["uuid"].as_str().unwrap().parse().unwrap();
+        assert!(get_project_dir(&server.working_dir, &uuid).is_dir());
+        let project_file = get_project_file(&server.working_dir, &uuid);
+        assert_eq!(fs::read(project_file).unwrap(), b"blend");
+    }
+}
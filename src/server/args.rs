@@ -1,3 +1,6 @@
+use crate::server::policy::PolicyKind;
+use crate::server::webhook::WebhookUrl;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -8,4 +11,46 @@ pub(crate) struct ServerArgs {
     /// Server port
     #[structopt(short = "p", long = "port", default_value = "4049")]
     pub port: u16,
+    /// Port to serve the HTTP/JSON API on (disabled if not specified)
+    #[structopt(long = "api-port")]
+    pub api_port: Option<u16>,
+    /// Persistent data directory (projects and frames are kept across restarts)
+    #[structopt(short = "d", long = "data-dir", parse(from_os_str))]
+    pub data_dir: Option<PathBuf>,
+    /// Seconds without a heartbeat before a busy worker is considered dead (the frame it was
+    /// rendering counts as a failed attempt)
+    #[structopt(long = "worker-timeout", default_value = "60")]
+    pub worker_timeout: u64,
+    /// Number of frames a worker can fail in a row before it stops receiving tasks (0 to disable)
+    #[structopt(long = "quarantine-after", default_value = "5")]
+    pub quarantine_after: u32,
+    /// Scheduling policy used to choose which project the next frame is taken from
+    /// (round-robin, fifo or fair-share)
+    #[structopt(long = "policy", default_value = "fair-share")]
+    pub policy: PolicyKind,
+    /// Shell command run when a frame has been rendered, with the TINYRF_PROJECT_UUID,
+    /// TINYRF_PROJECT_NAME, TINYRF_FRAME, TINYRF_OUTPUT and TINYRF_LOG environment variables set
+    #[structopt(long = "on-frame-completed")]
+    pub on_frame_completed: Option<String>,
+    /// Shell command run when a frame has failed and will not be retried, with the same
+    /// environment variables as --on-frame-completed (except TINYRF_OUTPUT) and TINYRF_ERROR
+    #[structopt(long = "on-frame-failed")]
+    pub on_frame_failed: Option<String>,
+    /// Shell command run when every frame of a project has been rendered, with the
+    /// TINYRF_PROJECT_UUID, TINYRF_PROJECT_NAME and TINYRF_OUTPUT (the directory of the frames)
+    /// environment variables set
+    #[structopt(long = "on-project-completed")]
+    pub on_project_completed: Option<String>,
+    /// Seconds a hook can run before it is killed, so that a hung hook does not hold up the
+    /// hooks of later events
+    #[structopt(long = "hook-timeout", default_value = "600")]
+    pub hook_timeout: u64,
+    /// HTTP URL to POST a JSON payload to when a project is added, completed or has failed
+    /// frames, or when a worker connects or disconnects (can be given more than once)
+    #[structopt(long = "webhook", number_of_values = 1)]
+    pub webhooks: Vec<WebhookUrl>,
+    /// Maximum number of times to try sending an event to a webhook, waiting longer after each
+    /// failed attempt (every attempt is recorded in webhooks.log in the working directory)
+    #[structopt(long = "webhook-attempts", default_value = "5")]
+    pub webhook_attempts: u32,
 }
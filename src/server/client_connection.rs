@@ -0,0 +1,319 @@
+use crate::common::file::{
+    get_frame_file, get_frame_log_file, get_project_dir, get_project_file, get_video_file,
+};
+use crate::common::message::{ClientMessage, ClientResponse};
+use crate::common::net::{read_json, write_json};
+use crate::common::render_task::Frame;
+use crate::common::settings::ProjectSettings;
+use crate::common::status::{ProjectStatus, WorkerStatus};
+use crate::common::transfer::{recv_file, send_file};
+use crate::common::video::VideoState;
+use crate::server::project::Project;
+use crate::server::scheduler::{find_project, list_projects, list_workers, SchedulerManageMessage};
+use crossbeam_channel::Sender;
+use failure::Fail;
+use log::{debug, error, info};
+use std::io::{BufRead, BufReader, BufWriter};
+use std::net::{IpAddr, TcpStream};
+use std::path::Path;
+use std::{fs, io};
+
+pub(super) struct ClientConnection<'a> {
+    addr: IpAddr,
+    reader: BufReader<&'a TcpStream>,
+    writer: BufWriter<&'a TcpStream>,
+    manage_send: Sender<SchedulerManageMessage>,
+    project_dir: &'a Path,
+}
+
+type ClientConnectionResult<T> = Result<T, ClientConnectionError>;
+
+#[derive(Fail, Debug)]
+enum ClientConnectionError {
+    #[fail(display = "I/O error: {}", 0)]
+    IoError(#[fail(cause)] io::Error),
+    #[fail(display = "Error transferring file: {}", 0)]
+    TransferFailed(#[fail(cause)] io::Error),
+}
+
+impl<'a> ClientConnection<'a> {
+    /// Handle an incoming client connection, starting with the first message
+    /// Messages are handled until the client disconnects
+    pub(super) fn handle(
+        addr: IpAddr,
+        reader: BufReader<&'a TcpStream>,
+        writer: BufWriter<&'a TcpStream>,
+        message: ClientMessage,
+        manage_send: Sender<SchedulerManageMessage>,
+        project_dir: &'a Path,
+    ) {
+        let mut connection = ClientConnection { addr, reader, writer, manage_send, project_dir };
+
+        debug!("Client connected: {}", &connection.addr);
+
+        // Handle messages until the client disconnects or an error occurs
+        let mut message = Some(message);
+        let result = loop {
+            let message = match message.take() {
+                Some(message) => message,
+                None => match connection.read_message() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break Ok(()),
+                    Err(error) => break Err(error),
+                },
+            };
+            if let Err(error) = connection.handle_message(message) {
+                break Err(error);
+            }
+        };
+        match result {
+            Ok(()) => debug!("Client disconnected: {}", &connection.addr),
+            Err(error) => error!("Client disconnected: {}: {}", &connection.addr, error),
+        }
+    }
+
+    /// Handle a message from the client
+    fn handle_message(&mut self, message: ClientMessage) -> ClientConnectionResult<()> {
+        match message {
+            ClientMessage::SubmitProject(settings) => self.submit_project(*settings),
+            ClientMessage::ListProjects => {
+                let projects = self.list_projects();
+                self.write_response(ClientResponse::Projects(projects))
+            }
+            ClientMessage::ProjectStatus { project } => match self.find_project(&project) {
+                Ok(project) => self.write_response(ClientResponse::Project(Box::new(project))),
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::RetryFailed { project } => match self.find_project(&project) {
+                Ok(project) if project.cancelled => {
+                    let error = format!("project \"{}\" has been cancelled", project.name);
+                    self.write_response(ClientResponse::Error(error))
+                }
+                Ok(project) => {
+                    debug!("Retrying failed frames of \"{}\" for {}", &project.name, &self.addr);
+                    self.send_manage_message(SchedulerManageMessage::RetryFailed(project.uuid));
+                    self.write_response(ClientResponse::Done)
+                }
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::PauseProject { project } => match self.find_project(&project) {
+                Ok(project) => {
+                    debug!("Pausing project \"{}\" for {}", &project.name, &self.addr);
+                    self.send_manage_message(SchedulerManageMessage::PauseProject(project.uuid));
+                    self.write_response(ClientResponse::Done)
+                }
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::ResumeProject { project } => match self.find_project(&project) {
+                Ok(project) if project.cancelled => {
+                    let error = format!("project \"{}\" has been cancelled", project.name);
+                    self.write_response(ClientResponse::Error(error))
+                }
+                Ok(project) => {
+                    debug!("Resuming project \"{}\" for {}", &project.name, &self.addr);
+                    self.send_manage_message(SchedulerManageMessage::ResumeProject(project.uuid));
+                    self.write_response(ClientResponse::Done)
+                }
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::CancelProject { project } => match self.find_project(&project) {
+                Ok(project) => {
+                    debug!("Cancelling project \"{}\" for {}", &project.name, &self.addr);
+                    self.send_manage_message(SchedulerManageMessage::CancelProject(project.uuid));
+                    self.write_response(ClientResponse::Done)
+                }
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::RemoveProject { project } => match self.find_project(&project) {
+                Ok(project) => {
+                    debug!("Removing project \"{}\" for {}", &project.name, &self.addr);
+                    self.send_manage_message(SchedulerManageMessage::RemoveProject(project.uuid));
+                    self.write_response(ClientResponse::Done)
+                }
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::FetchOutput { project, frame } => match self.find_project(&project) {
+                Ok(project) => self.send_output(project, frame),
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::FetchLog { project, frame } => match self.find_project(&project) {
+                Ok(project) => self.send_log(project, frame),
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::FetchVideo { project } => match self.find_project(&project) {
+                Ok(project) => self.send_video(project),
+                Err(error) => self.write_response(ClientResponse::Error(error)),
+            },
+            ClientMessage::ListWorkers => {
+                let workers = self.list_workers();
+                self.write_response(ClientResponse::Workers(workers))
+            }
+            ClientMessage::ReleaseWorker { worker } => {
+                if !self.list_workers().iter().any(|status| status.name == worker) {
+                    let error = format!("worker \"{}\" not found", worker);
+                    return self.write_response(ClientResponse::Error(error));
+                }
+                debug!("Releasing worker {} for {}", &worker, &self.addr);
+                self.send_manage_message(SchedulerManageMessage::ReleaseWorker(worker));
+                self.write_response(ClientResponse::Done)
+            }
+        }
+    }
+
+    /// Receive a project from the client and send it to the scheduler
+    /// If the project shares the project file of another project, the upload is skipped
+    fn submit_project(&mut self, settings: ProjectSettings) -> ClientConnectionResult<()> {
+        let project = match new_project(settings, &self.manage_send) {
+            Ok(project) => project,
+            Err(error) => return self.write_response(ClientResponse::Error(error)),
+        };
+        // Create the project directory and receive the project file
+        store_project(self.project_dir, &project, |project_file| {
+            self.write_response(ClientResponse::UploadReady)?;
+            recv_file(&mut self.reader, &mut self.writer, project_file)
+                .map_err(ClientConnectionError::TransferFailed)
+        })?;
+        info!("Project \"{}\" submitted by {}", &project, &self.addr);
+        // Send the project to the scheduler
+        let project_uuid = project.uuid;
+        self.send_manage_message(SchedulerManageMessage::AddProject(Box::new(project)));
+        self.write_response(ClientResponse::ProjectAdded(project_uuid))
+    }
+
+    /// Send the output file of a completed frame to the client
+    fn send_output(&mut self, project: ProjectStatus, frame: Frame) -> ClientConnectionResult<()> {
+        // Check that the frame has been completed
+        if !project.completed_frames.contains(&frame) {
+            let error = format!("frame {} of \"{}\" has not been completed", frame, project.name);
+            return self.write_response(ClientResponse::Error(error));
+        }
+        // Send the output file
+        self.write_response(ClientResponse::OutputReady)?;
+        let output_file =
+            get_frame_file(self.project_dir, &project.uuid, frame, project.output_ext);
+        send_file(&mut self.reader, &mut self.writer, &output_file)
+            .map_err(ClientConnectionError::TransferFailed)
+    }
+
+    /// Send the render log of a frame to the client
+    fn send_log(&mut self, project: ProjectStatus, frame: Frame) -> ClientConnectionResult<()> {
+        // Check that the frame has been rendered
+        let log_file = get_frame_log_file(self.project_dir, &project.uuid, frame);
+        if !log_file.is_file() {
+            let error = format!("frame {} of \"{}\" has no render log", frame, project.name);
+            return self.write_response(ClientResponse::Error(error));
+        }
+        // Send the log file
+        self.write_response(ClientResponse::OutputReady)?;
+        send_file(&mut self.reader, &mut self.writer, &log_file)
+            .map_err(ClientConnectionError::TransferFailed)
+    }
+
+    /// Send the video encoded from the frames of a project to the client
+    fn send_video(&mut self, project: ProjectStatus) -> ClientConnectionResult<()> {
+        // Check that the video has been encoded
+        let codec = match (&project.video, &project.video_state) {
+            (Some(settings), Some(VideoState::Finished)) => settings.codec,
+            _ => {
+                let error = format!("the video of \"{}\" has not been encoded", project.name);
+                return self.write_response(ClientResponse::Error(error));
+            }
+        };
+        // Send the video file
+        self.write_response(ClientResponse::OutputReady)?;
+        let video_file = get_video_file(self.project_dir, &project.uuid, codec);
+        send_file(&mut self.reader, &mut self.writer, &video_file)
+            .map_err(ClientConnectionError::TransferFailed)
+    }
+
+    /// Get the status of all of the projects from the scheduler
+    fn list_projects(&mut self) -> Vec<ProjectStatus> {
+        list_projects(&self.manage_send)
+    }
+
+    /// Get the status of all of the workers from the scheduler
+    fn list_workers(&mut self) -> Vec<WorkerStatus> {
+        list_workers(&self.manage_send)
+    }
+
+    /// Find the project a reference (a UUID, UUID prefix, or name) refers to
+    fn find_project(&mut self, reference: &str) -> Result<ProjectStatus, String> {
+        find_project(&self.manage_send, reference)
+    }
+
+    /// Send a management message to the scheduler
+    fn send_manage_message(&mut self, message: SchedulerManageMessage) {
+        self.manage_send.send(message).unwrap();
+    }
+
+    /// Read a message from the client (blocking), returning `None` if the client disconnected
+    fn read_message(&mut self) -> ClientConnectionResult<Option<ClientMessage>> {
+        // Check for the end of the stream before reading the next message
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let message = read_json(&mut self.reader)?;
+        debug!("{} -> {:?}", &self.addr, &message);
+        Ok(Some(message))
+    }
+
+    /// Send a response to the client
+    fn write_response(&mut self, response: ClientResponse) -> ClientConnectionResult<()> {
+        debug!("{} <- {:?}", &self.addr, &response);
+        Ok(write_json(&mut self.writer, response)?)
+    }
+}
+
+/// Create a project from the settings submitted by a client, returning a description of the
+/// problem if they are invalid
+/// The project file has to be uploaded unless the project shares the file of another project
+pub(super) fn new_project(
+    mut settings: ProjectSettings,
+    manage_send: &Sender<SchedulerManageMessage>,
+) -> Result<Project, String> {
+    // Check that the settings are valid
+    settings.validate()?;
+    // Find the project to share the project file with
+    let shared_project = match settings.shared_file.as_deref() {
+        Some(reference) => Some(find_project(manage_send, reference)?),
+        None => None,
+    };
+    // Default to the name of the other project, along with the scene if one was selected
+    if let Some(shared_project) = shared_project.as_ref().filter(|_| settings.name.is_empty()) {
+        settings.name = match &settings.overrides.scene {
+            Some(scene) => format!("{} ({})", shared_project.name, scene),
+            None => shared_project.name.clone(),
+        };
+    }
+    let mut project = Project::new(settings);
+    // Use the project file of the other project (or the one it shares)
+    if let Some(shared_project) = shared_project {
+        project.shared_file = Some(shared_project.shared_file.unwrap_or(shared_project.uuid));
+    }
+    Ok(project)
+}
+
+/// Create the directory of a new project and receive its project file, unless the project
+/// shares the file of another project
+/// The directory is removed if the project file could not be received
+pub(super) fn store_project<E: From<io::Error>>(
+    project_dir: &Path,
+    project: &Project,
+    receive_file: impl FnOnce(&Path) -> Result<(), E>,
+) -> Result<(), E> {
+    let dir = get_project_dir(project_dir, &project.uuid);
+    fs::create_dir(&dir)?;
+    if project.shared_file.is_none() {
+        if let Err(error) = receive_file(&get_project_file(project_dir, &project.uuid)) {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+impl From<io::Error> for ClientConnectionError {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
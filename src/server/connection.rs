@@ -1,17 +1,28 @@
-use crate::common::file::{get_output_file, get_project_file};
+use crate::common::file::{
+    get_frame_file, get_frame_log_file, get_log_file, get_project_dir, get_project_file,
+};
 use crate::common::message::{ServerMessage, WorkerMessage};
-use crate::common::net::{read_json, write_json};
-use crate::common::render_task::{RenderTask, RenderTaskResult};
+use crate::common::net::{read_json_resumable, write_json};
+use crate::common::render_task::{
+    Frame, RenderFailure, RenderFailureKind, RenderTask, RenderTaskResult,
+};
 use crate::common::transfer::{recv_file, send_file};
-use crate::server::scheduler::{SchedulerRenderMessage, SchedulerResultMessage};
+use crate::server::scheduler::{
+    SchedulerControlMessage, SchedulerRenderMessage, SchedulerResultMessage, WorkerId,
+};
 use crossbeam_channel::internal::SelectHandle;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{select, Receiver, Sender};
 use failure::Fail;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use std::io::{BufReader, BufWriter};
 use std::net::{IpAddr, TcpStream};
-use std::path::Path;
-use std::{fmt, io};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs, io};
+use uuid::Uuid;
+
+/// How often to check for messages from the scheduler while waiting for the worker
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 pub(super) struct Connection<'a> {
     name: Option<String>,
@@ -20,7 +31,20 @@ pub(super) struct Connection<'a> {
     writer: BufWriter<&'a TcpStream>,
     render_recv: Receiver<SchedulerRenderMessage>,
     result_send: Sender<SchedulerResultMessage>,
+    control_recv: Receiver<SchedulerControlMessage>,
     project_dir: &'a Path,
+    lease: Duration,
+    quarantined: bool,
+    /// The task being rendered
+    task: Option<RenderTask>,
+    /// Whether the worker has been told to abort the task being rendered
+    aborting: bool,
+    /// The frame of the task the worker last reported progress on
+    current_frame: Option<Frame>,
+    /// A task sent by the scheduler while another task was being rendered
+    next_task: Option<RenderTask>,
+    /// A partially-read message from the worker
+    line: Vec<u8>,
 }
 
 type ConnectionResult<T> = Result<T, ConnectionError>;
@@ -33,95 +57,343 @@ enum ConnectionError {
     TransferFailed(#[fail(cause)] io::Error),
     #[fail(display = "Unexpected message: {:?}", 0)]
     UnexpectedMessage(WorkerMessage),
+    #[fail(display = "No message received for {:?}", 0)]
+    LeaseExpired(Duration),
+}
+
+/// The outcome of sending a render task to a worker
+enum RenderOutcome {
+    /// The render finished with result
+    Finished(RenderTaskResult),
+    /// The render was aborted by the scheduler
+    Aborted,
 }
 
-impl Connection<'_> {
-    /// Handle an incoming worker connection
+impl<'a> Connection<'a> {
+    /// Handle an incoming worker connection, starting with the init message
+    /// The worker is considered dead if it sends nothing for the duration of the lease
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn handle(
-        stream: TcpStream,
+        addr: IpAddr,
+        reader: BufReader<&'a TcpStream>,
+        writer: BufWriter<&'a TcpStream>,
+        message: WorkerMessage,
         render_recv: Receiver<SchedulerRenderMessage>,
         result_send: Sender<SchedulerResultMessage>,
-        project_dir: &'_ Path,
+        project_dir: &'a Path,
+        lease: Duration,
     ) {
+        let (control_send, control_recv) = crossbeam_channel::unbounded();
         let mut connection = Connection {
             name: None,
-            addr: stream.peer_addr().unwrap().ip(),
-            reader: BufReader::new(&stream),
-            writer: BufWriter::new(&stream),
+            addr,
+            reader,
+            writer,
             render_recv,
             result_send,
+            control_recv,
             project_dir,
+            lease,
+            quarantined: false,
+            task: None,
+            aborting: false,
+            current_frame: None,
+            next_task: None,
+            line: Vec::new(),
         };
 
-        debug!("Incoming connection from {}", &connection.addr);
-
-        // Read the init message from the worker
-        if let Ok(WorkerMessage::Init { name }) = connection.read_message() {
+        // Check the init message from the worker
+        if let WorkerMessage::Init { name } = message {
             // Set the worker name
             connection.name = name;
 
             info!("Worker connected: {}", connection);
 
+            // Reads time out regularly so that messages from the scheduler can be handled
+            let timeout = Some(CONTROL_POLL_INTERVAL);
+            if let Err(error) = connection.reader.get_ref().set_read_timeout(timeout) {
+                error!("Worker disconnected: {}: {}", connection, error);
+                return;
+            }
+
+            // Register the connection with the scheduler
+            let worker = WorkerId { connection: Uuid::new_v4(), name: connection.to_string() };
+            let message = SchedulerResultMessage::Connected(worker.clone(), control_send);
+            connection.result_send.send(message).unwrap();
+
             // Wait for and handle render tasks until an error occurs
             let error = loop {
                 // Let the worker know if there are no tasks currently available
-                if !connection.render_recv.is_ready() {
+                if connection.quarantined
+                    || (!connection.render_recv.is_ready() && connection.control_recv.is_empty())
+                {
                     if let Err(error) = connection.write_message(ServerMessage::Idle) {
                         break error;
                     }
                 }
                 // Wait for a render task from the scheduler
-                let render_task = connection.render_recv.recv().unwrap().0;
+                let render_task = connection.recv_task();
                 debug!("Received task from scheduler: {:?}", &render_task);
+                // Skip tasks of projects that were removed after the task was sent
+                if !get_project_file(project_dir, &render_task.file_uuid).is_file() {
+                    debug!("Skipping task of removed project {}", &render_task.project_uuid);
+                    let message = SchedulerResultMessage::Lost(render_task, worker.clone());
+                    connection.result_send.send(message).unwrap();
+                    continue;
+                }
+                // Let the scheduler know which worker is rendering the task
+                let message = SchedulerResultMessage::Started(render_task.clone(), worker.clone());
+                connection.result_send.send(message).unwrap();
                 // Send the task to the worker and get the result
+                connection.task = Some(render_task.clone());
                 let result = connection.handle_render_task(render_task.clone());
+                connection.task = None;
+                connection.aborting = false;
+                let current_frame = connection.current_frame.take();
                 // Handle the result
                 match result {
                     // Task finished with result
-                    Ok(result) => connection.send_result(render_task, result),
-                    // Communication error
+                    Ok(RenderOutcome::Finished(result)) => {
+                        connection.send_result(render_task, &worker, result)
+                    }
+                    // Task was aborted (the frames were not rendered, but the worker is still fine)
+                    Ok(RenderOutcome::Aborted) => {
+                        let message = SchedulerResultMessage::Aborted(render_task, worker.clone());
+                        connection.result_send.send(message).unwrap();
+                    }
+                    // Communication error (the frames can be rendered again by another worker)
                     Err(error) => {
-                        connection.send_result(render_task, Err(()));
+                        // Remove any partially-uploaded output so that it is not resumed
+                        for frame in render_task.frames() {
+                            let _ = fs::remove_file(connection.output_file(&render_task, frame));
+                        }
+                        // A worker that stops responding while rendering may have been hung by
+                        // the frame, so it counts as a failure of the frame it was rendering
+                        let message = match &error {
+                            ConnectionError::LeaseExpired(_) => {
+                                let frame = current_frame.unwrap_or(render_task.start_frame);
+                                let failure = RenderFailure {
+                                    kind: RenderFailureKind::LeaseExpired,
+                                    message: error.to_string(),
+                                    exit_code: None,
+                                    signal: None,
+                                    stderr_tail: Vec::new(),
+                                };
+                                SchedulerResultMessage::Expired(
+                                    render_task,
+                                    worker.clone(),
+                                    frame,
+                                    failure,
+                                )
+                            }
+                            _ => SchedulerResultMessage::Lost(render_task, worker.clone()),
+                        };
+                        connection.result_send.send(message).unwrap();
                         break error;
                     }
                 }
             };
             error!("Worker disconnected: {}: {}", connection, error);
+            connection.result_send.send(SchedulerResultMessage::Disconnected(worker)).unwrap();
+        }
+    }
+
+    /// Wait for a render task, either from the render channel or sent directly by the scheduler
+    /// A quarantined worker only receives tasks sent directly to it
+    fn recv_task(&mut self) -> RenderTask {
+        if let Some(render_task) = self.next_task.take() {
+            return render_task;
+        }
+        loop {
+            let message = if self.quarantined {
+                self.control_recv.recv().unwrap()
+            } else {
+                select! {
+                    recv(self.render_recv) -> message => return message.unwrap().0,
+                    recv(self.control_recv) -> message => message.unwrap(),
+                }
+            };
+            match message {
+                SchedulerControlMessage::Render(render_task) => return render_task,
+                SchedulerControlMessage::SetQuarantined(quarantined) => {
+                    self.set_quarantined(quarantined)
+                }
+                // The task already finished
+                SchedulerControlMessage::Abort(_) => (),
+            }
         }
     }
 
+    /// Handle the messages sent by the scheduler while a task is being rendered
+    fn handle_control_messages(&mut self) -> ConnectionResult<()> {
+        while let Ok(message) = self.control_recv.try_recv() {
+            match message {
+                // Render the task after the current one
+                SchedulerControlMessage::Render(render_task) => self.next_task = Some(render_task),
+                SchedulerControlMessage::SetQuarantined(quarantined) => {
+                    self.set_quarantined(quarantined)
+                }
+                // Tell the worker to stop rendering the task
+                SchedulerControlMessage::Abort(render_task) => {
+                    if !self.aborting && self.task.as_ref() == Some(&render_task) {
+                        self.write_message(ServerMessage::AbortRender)?;
+                        self.aborting = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop or resume taking tasks from the render channel
+    fn set_quarantined(&mut self, quarantined: bool) {
+        if quarantined {
+            warn!("Worker quarantined: {}", self);
+        } else {
+            info!("Worker released from quarantine: {}", self);
+        }
+        self.quarantined = quarantined;
+    }
+
     /// Send a render task to the worker and get the result back
-    fn handle_render_task(
-        &mut self,
-        render_task: RenderTask,
-    ) -> ConnectionResult<RenderTaskResult> {
+    fn handle_render_task(&mut self, render_task: RenderTask) -> ConnectionResult<RenderOutcome> {
         // Get the project file
-        let project_file = get_project_file(self.project_dir, &render_task.project_uuid);
+        let project_file = get_project_file(self.project_dir, &render_task.file_uuid);
         // Send the render information to the worker
         self.write_message(ServerMessage::StartRender(render_task.clone()))?;
         // Send the project file to the worker
-        send_file(&mut self.reader, &mut self.writer, &project_file)
-            .map_err(ConnectionError::TransferFailed)?;
-        // Wait for a result message from the worker
-        match self.read_message()? {
-            WorkerMessage::RenderResult(result) => {
-                // If the result was success, download the output from the worker
-                if result.is_ok() {
-                    let output_file = get_output_file(self.project_dir, &render_task);
-                    recv_file(&mut self.reader, &mut self.writer, &output_file)
-                        .map_err(ConnectionError::TransferFailed)?;
+        self.transfer(|reader, writer| send_file(reader, writer, &project_file))?;
+        // Wait for a result message from the worker, renewing the lease on every heartbeat or
+        // progress update
+        let result = loop {
+            match self.read_message()? {
+                WorkerMessage::Heartbeat => continue,
+                WorkerMessage::Progress(progress) => {
+                    if let Some(frame) =
+                        progress.frame.filter(|frame| render_task.frames().contains(frame))
+                    {
+                        self.current_frame = Some(frame);
+                    }
+                    let message = SchedulerResultMessage::Progress(render_task.clone(), progress);
+                    self.result_send.send(message).unwrap();
+                }
+                WorkerMessage::RenderResult(result) => break result,
+                WorkerMessage::RenderAborted => return Ok(RenderOutcome::Aborted),
+                message => return Err(ConnectionError::UnexpectedMessage(message)),
+            }
+        };
+        // Check that there is a result for every frame of the task
+        if !result.keys().copied().eq(render_task.frames()) {
+            return Err(ConnectionError::UnexpectedMessage(WorkerMessage::RenderResult(result)));
+        }
+        // Let the worker know that the uploads can start, so that it can skip an abort message
+        // that was sent before the result arrived
+        self.write_message(ServerMessage::UploadReady)?;
+        // Download the output of each frame that was rendered from the worker
+        // If the project is removed during the uploads, the rest of them are still received (and
+        // discarded) so that the worker stays in step with the connection
+        let mut kept = true;
+        for (&frame, _) in result.iter().filter(|(_, result)| result.is_ok()) {
+            let output_file = self.output_file(&render_task, frame);
+            kept &= self.recv_project_file(&render_task, &output_file)?;
+        }
+        // Download the render log, replacing the log of any previous attempt
+        let log_file = get_log_file(self.project_dir, &render_task);
+        if log_file.is_file() {
+            let _ = fs::remove_file(&log_file);
+        }
+        kept &= self.recv_project_file(&render_task, &log_file)?;
+        // Every frame of the task shares the same log
+        if kept {
+            for frame in render_task.frames().skip(1) {
+                let frame_log_file =
+                    get_frame_log_file(self.project_dir, &render_task.project_uuid, frame);
+                if let Err(error) = fs::copy(&log_file, frame_log_file) {
+                    if !self.project_removed(&render_task) {
+                        return Err(error.into());
+                    }
+                    kept = false;
+                    break;
                 }
-                Ok(result)
             }
-            message => Err(ConnectionError::UnexpectedMessage(message)),
         }
+        if !kept {
+            info!("Discarded the output of {}, the project was removed", &render_task);
+            return Ok(RenderOutcome::Aborted);
+        }
+        Ok(RenderOutcome::Finished(result))
     }
 
-    /// Read a message from the worker (blocking)
+    /// Receive a file into the directory of the project of a render task, discarding it if the
+    /// project has been removed
+    /// Returns whether the file was kept
+    fn recv_project_file(
+        &mut self,
+        render_task: &RenderTask,
+        file: &Path,
+    ) -> ConnectionResult<bool> {
+        if !self.project_removed(render_task) {
+            match self.transfer(|reader, writer| recv_file(reader, writer, file)) {
+                Ok(()) => return Ok(true),
+                // The file could not be created because the project was removed after the check
+                // (nothing has been sent yet, so the file can still be discarded)
+                Err(ConnectionError::TransferFailed(error))
+                    if error.kind() == io::ErrorKind::NotFound
+                        && self.project_removed(render_task) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        let discard_file = env::temp_dir().join(format!("tinyrf-discard-{}", Uuid::new_v4()));
+        let result = self.transfer(|reader, writer| recv_file(reader, writer, &discard_file));
+        let _ = fs::remove_file(&discard_file);
+        result.map(|()| false)
+    }
+
+    /// Check whether the project of a render task has been removed
+    fn project_removed(&self, render_task: &RenderTask) -> bool {
+        !get_project_dir(self.project_dir, &render_task.project_uuid).is_dir()
+    }
+
+    /// Get the path to the output file of a frame of a render task
+    fn output_file(&self, render_task: &RenderTask, frame: Frame) -> PathBuf {
+        get_frame_file(self.project_dir, &render_task.project_uuid, frame, render_task.output_ext)
+    }
+
+    /// Transfer a file, waiting for up to the lease for each read
+    fn transfer(
+        &mut self,
+        transfer: impl FnOnce(&mut BufReader<&TcpStream>, &mut BufWriter<&TcpStream>) -> io::Result<()>,
+    ) -> ConnectionResult<()> {
+        self.reader.get_ref().set_read_timeout(Some(self.lease))?;
+        transfer(&mut self.reader, &mut self.writer).map_err(ConnectionError::TransferFailed)?;
+        self.reader.get_ref().set_read_timeout(Some(CONTROL_POLL_INTERVAL))?;
+        Ok(())
+    }
+
+    /// Read a message from the worker, handling messages from the scheduler while waiting
+    /// The lease expires if the worker sends nothing for its duration
     fn read_message(&mut self) -> ConnectionResult<WorkerMessage> {
-        let message = read_json(&mut self.reader)?;
-        debug!("{} -> {:?}", &self.addr, &message);
-        Ok(message)
+        let start = Instant::now();
+        loop {
+            match read_json_resumable(&mut self.reader, &mut self.line) {
+                Ok(message) => {
+                    debug!("{} -> {:?}", &self.addr, &message);
+                    return Ok(message);
+                }
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if start.elapsed() >= self.lease {
+                        return Err(ConnectionError::LeaseExpired(self.lease));
+                    }
+                    self.handle_control_messages()?;
+                }
+                Err(error) => return Err(ConnectionError::IoError(error)),
+            }
+        }
     }
 
     /// Send a message to the worker
@@ -131,8 +403,14 @@ impl Connection<'_> {
     }
 
     /// Send the result of a render task to the scheduler
-    fn send_result(&mut self, render_task: RenderTask, result: RenderTaskResult) {
-        self.result_send.send(SchedulerResultMessage(render_task, result)).unwrap();
+    fn send_result(
+        &mut self,
+        render_task: RenderTask,
+        worker: &WorkerId,
+        result: RenderTaskResult,
+    ) {
+        let message = SchedulerResultMessage::Finished(render_task, worker.clone(), result);
+        self.result_send.send(message).unwrap();
     }
 }
 
@@ -0,0 +1,331 @@
+use crate::common::file::{get_frame_file, get_frame_log_file, get_project_dir};
+use crate::common::render_task::Frame;
+use crate::server::project::Project;
+use crate::server::webhook::{self, WebhookUrl};
+use crossbeam_channel::{Receiver, Sender};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// How often to check whether a hook has exited
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something that happened on the server, which hooks are run and webhooks are sent for
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Event {
+    #[serde(rename = "event")]
+    pub kind: EventKind,
+    /// When the event happened, in seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_uuid: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    /// The frame the event is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<Frame>,
+    /// The number of frames of the project that failed, for failed frames events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_failed: Option<Frame>,
+    /// The output file of the frame, or the project directory for project events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// The render log of the frame, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+    /// A description of the error, for failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The name of the worker, for worker events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker: Option<String>,
+}
+
+/// The kinds of events that can happen
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum EventKind {
+    ProjectAdded,
+    FrameCompleted,
+    FrameFailed,
+    ProjectCompleted,
+    /// Every frame of a project that could be rendered has been, but some of them failed
+    FramesFailed,
+    WorkerConnected,
+    WorkerDisconnected,
+}
+
+/// The commands run when events happen
+#[derive(Debug, Default)]
+pub(super) struct Hooks {
+    pub frame_completed: Option<String>,
+    pub frame_failed: Option<String>,
+    pub project_completed: Option<String>,
+    /// How long a hook can run before it is killed
+    pub timeout: Duration,
+}
+
+/// The URLs events are sent to, and how hard to try
+pub(super) struct Webhooks {
+    pub urls: Vec<WebhookUrl>,
+    /// The maximum number of times to try sending an event to a URL
+    pub max_attempts: u32,
+    /// The file every attempt at sending an event is recorded in
+    pub delivery_log: File,
+}
+
+impl Event {
+    /// Create an event with only the kind and time set
+    fn new(kind: EventKind) -> Event {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        Event {
+            kind,
+            timestamp,
+            project_uuid: None,
+            project_name: None,
+            frame: None,
+            num_failed: None,
+            output: None,
+            log: None,
+            error: None,
+            worker: None,
+        }
+    }
+
+    /// Create an event about a project
+    fn with_project(kind: EventKind, project: &Project) -> Event {
+        Event {
+            project_uuid: Some(project.uuid),
+            project_name: Some(project.name.clone()),
+            ..Event::new(kind)
+        }
+    }
+
+    /// Create an event for a project that has been added to the scheduler
+    pub(super) fn project_added(project: &Project) -> Event {
+        Event::with_project(EventKind::ProjectAdded, project)
+    }
+
+    /// Create an event for a frame that has been rendered
+    pub(super) fn frame_completed(project: &Project, frame: Frame, working_dir: &Path) -> Event {
+        Event {
+            frame: Some(frame),
+            output: Some(get_frame_file(working_dir, &project.uuid, frame, project.output_ext)),
+            log: Some(get_frame_log_file(working_dir, &project.uuid, frame)),
+            ..Event::with_project(EventKind::FrameCompleted, project)
+        }
+    }
+
+    /// Create an event for a frame that failed and will not be retried
+    pub(super) fn frame_failed(
+        project: &Project,
+        frame: Frame,
+        error: String,
+        working_dir: &Path,
+    ) -> Event {
+        Event {
+            frame: Some(frame),
+            log: Some(get_frame_log_file(working_dir, &project.uuid, frame)),
+            error: Some(error),
+            ..Event::with_project(EventKind::FrameFailed, project)
+        }
+    }
+
+    /// Create an event for a project whose frames have all been rendered
+    pub(super) fn project_completed(project: &Project, working_dir: &Path) -> Event {
+        Event {
+            output: Some(get_project_dir(working_dir, &project.uuid)),
+            ..Event::with_project(EventKind::ProjectCompleted, project)
+        }
+    }
+
+    /// Create an event for a project that has no frames left to render, but has failed frames
+    pub(super) fn frames_failed(project: &Project) -> Event {
+        Event {
+            num_failed: Some(project.num_failed()),
+            ..Event::with_project(EventKind::FramesFailed, project)
+        }
+    }
+
+    /// Create an event for a worker that has connected
+    pub(super) fn worker_connected(worker: &str) -> Event {
+        Event { worker: Some(worker.to_string()), ..Event::new(EventKind::WorkerConnected) }
+    }
+
+    /// Create an event for a worker that has disconnected
+    pub(super) fn worker_disconnected(worker: &str) -> Event {
+        Event { worker: Some(worker.to_string()), ..Event::new(EventKind::WorkerDisconnected) }
+    }
+}
+
+impl EventKind {
+    /// Check whether the event is about a single frame
+    fn is_frame_event(self) -> bool {
+        matches!(self, Self::FrameCompleted | Self::FrameFailed)
+    }
+}
+
+impl Hooks {
+    /// Get the command to run for a kind of event, if any
+    fn command(&self, kind: EventKind) -> Option<&str> {
+        match kind {
+            EventKind::FrameCompleted => self.frame_completed.as_deref(),
+            EventKind::FrameFailed => self.frame_failed.as_deref(),
+            EventKind::ProjectCompleted => self.project_completed.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Start handling events in a new thread, so that slow hooks do not hold up the scheduler
+/// Hooks are run one at a time in their own thread, in the order the events happened, and each
+/// webhook URL has its own thread so that neither a slow hook nor an unreachable URL holds up
+/// the webhooks
+pub(super) fn start(hooks: Hooks, webhooks: Webhooks) -> Sender<Event> {
+    let (event_send, event_recv) = crossbeam_channel::unbounded::<Event>();
+    let (hook_send, hook_recv) = crossbeam_channel::unbounded::<Event>();
+    thread::spawn(move || {
+        for event in hook_recv {
+            if let Some(command) = hooks.command(event.kind) {
+                run_hook(command, &event, hooks.timeout);
+            }
+        }
+    });
+    let (max_attempts, delivery_log) =
+        (webhooks.max_attempts, Arc::new(Mutex::new(webhooks.delivery_log)));
+    let webhook_sends: Vec<_> = webhooks
+        .urls
+        .into_iter()
+        .map(|url| webhook::start(url, max_attempts, delivery_log.clone()))
+        .collect();
+    thread::spawn(move || {
+        for event in event_recv {
+            // Frame events are only handled by hooks, since a project can have thousands
+            if !webhook_sends.is_empty() && !event.kind.is_frame_event() {
+                let payload = Arc::new(serde_json::to_string(&event).unwrap());
+                for webhook_send in &webhook_sends {
+                    let _ = webhook_send.send((event.kind, payload.clone()));
+                }
+            }
+            let _ = hook_send.send(event);
+        }
+    });
+    event_send
+}
+
+/// Run a hook command with environment variables describing the event, logging how it exited
+/// The hook is killed if it runs for longer than the timeout
+fn run_hook(command: &str, event: &Event, timeout: Duration) {
+    debug!("Running hook {} for {}", event.kind, event);
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command).env("TINYRF_EVENT", event.kind.to_string()).stdin(Stdio::null());
+    if let Some(project_uuid) = event.project_uuid {
+        process.env("TINYRF_PROJECT_UUID", project_uuid.to_string());
+    }
+    if let Some(project_name) = &event.project_name {
+        process.env("TINYRF_PROJECT_NAME", project_name);
+    }
+    if let Some(frame) = event.frame {
+        process.env("TINYRF_FRAME", frame.to_string());
+    }
+    if let Some(output) = &event.output {
+        process.env("TINYRF_OUTPUT", output);
+    }
+    if let Some(log) = &event.log {
+        process.env("TINYRF_LOG", log);
+    }
+    if let Some(error) = &event.error {
+        process.env("TINYRF_ERROR", error);
+    }
+    let mut child = match process.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            error!("Error running hook {} for {}: {}", event.kind, event, error);
+            return;
+        }
+    };
+    // Read the output in other threads, so that a hook that writes a lot does not block
+    let stdout_reader = child.stdout.take().map(read_output);
+    let stderr_reader = child.stderr.take().map(read_output);
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                // The output is not waited for, since processes started by the hook may still
+                // have it open
+                warn!(
+                    "Hook {} for {} timed out after {:?} and was killed",
+                    event.kind, event, timeout
+                );
+                return;
+            }
+            Ok(None) => thread::sleep(HOOK_POLL_INTERVAL),
+            Err(error) => {
+                error!("Error running hook {} for {}: {}", event.kind, event, error);
+                return;
+            }
+        }
+    };
+    // Processes started by the hook may keep the output open after it exits, so the output is
+    // only waited for until the deadline
+    for reader in stdout_reader.into_iter().chain(stderr_reader) {
+        let output = reader.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        for line in output.unwrap_or_default().lines() {
+            debug!("  {}", line);
+        }
+    }
+    if status.success() {
+        info!("Hook {} for {} exited with {}", event.kind, event, status);
+    } else {
+        warn!("Hook {} for {} exited with {}", event.kind, event, status);
+    }
+}
+
+/// Read a stream of the output of a hook in a new thread until it is closed
+fn read_output(mut stream: impl Read + Send + 'static) -> Receiver<String> {
+    let (output_send, output_recv) = crossbeam_channel::bounded(1);
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stream.read_to_end(&mut output);
+        let _ = output_send.send(String::from_utf8_lossy(&output).into_owned());
+    });
+    output_recv
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.project_uuid, self.frame, &self.worker) {
+            (Some(project_uuid), Some(frame), _) => {
+                write!(f, "project {} frame {}", project_uuid, frame)
+            }
+            (Some(project_uuid), None, _) => write!(f, "project {}", project_uuid),
+            (None, _, Some(worker)) => write!(f, "worker {}", worker),
+            (None, _, None) => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ProjectAdded => write!(f, "project_added"),
+            Self::FrameCompleted => write!(f, "frame_completed"),
+            Self::FrameFailed => write!(f, "frame_failed"),
+            Self::ProjectCompleted => write!(f, "project_completed"),
+            Self::FramesFailed => write!(f, "frames_failed"),
+            Self::WorkerConnected => write!(f, "worker_connected"),
+            Self::WorkerDisconnected => write!(f, "worker_disconnected"),
+        }
+    }
+}
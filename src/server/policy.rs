@@ -0,0 +1,237 @@
+use crate::server::project::Project;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Decides which project the next frame is taken from
+pub(super) trait SchedulingPolicy: Send {
+    /// Choose the project to take the next frame from
+    /// The candidates are the projects that have a frame that can be assigned, in the order in
+    /// which they were queued
+    fn next_project(&mut self, candidates: &[&Project]) -> Option<Uuid>;
+
+    /// Record that a frame of a project has been assigned
+    fn frame_assigned(&mut self, _project: &Project) {}
+
+    /// Forget a project that has been removed
+    fn project_removed(&mut self, _project_uuid: &Uuid) {}
+}
+
+/// The available scheduling policies
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum PolicyKind {
+    RoundRobin,
+    Fifo,
+    FairShare,
+}
+
+impl PolicyKind {
+    /// Create a new instance of the policy
+    pub(super) fn create(self) -> Box<dyn SchedulingPolicy> {
+        match self {
+            Self::RoundRobin => Box::new(RoundRobin::default()),
+            Self::Fifo => Box::new(Fifo),
+            Self::FairShare => Box::new(FairShare::default()),
+        }
+    }
+}
+
+impl fmt::Display for PolicyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RoundRobin => write!(f, "round-robin"),
+            Self::Fifo => write!(f, "fifo"),
+            Self::FairShare => write!(f, "fair-share"),
+        }
+    }
+}
+
+impl FromStr for PolicyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "round-robin" => Ok(Self::RoundRobin),
+            "fifo" => Ok(Self::Fifo),
+            "fair-share" => Ok(Self::FairShare),
+            _ => Err(format!("unknown policy: {}", s)),
+        }
+    }
+}
+
+/// Take one frame from each project in turn, ignoring priorities and weights
+#[derive(Default)]
+struct RoundRobin {
+    /// The turn in which each project last had a frame assigned
+    last_turn: HashMap<Uuid, u64>,
+    turn: u64,
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn next_project(&mut self, candidates: &[&Project]) -> Option<Uuid> {
+        // Projects that have never had a turn go first, in the order they were queued
+        let last_turn = &self.last_turn;
+        candidates
+            .iter()
+            .min_by_key(|project| last_turn.get(&project.uuid).copied().unwrap_or(0))
+            .map(|project| project.uuid)
+    }
+
+    fn frame_assigned(&mut self, project: &Project) {
+        self.turn += 1;
+        self.last_turn.insert(project.uuid, self.turn);
+    }
+
+    fn project_removed(&mut self, project_uuid: &Uuid) {
+        self.last_turn.remove(project_uuid);
+    }
+}
+
+/// Render the projects one at a time, highest priority first and then in the order they were
+/// submitted
+struct Fifo;
+
+impl SchedulingPolicy for Fifo {
+    fn next_project(&mut self, candidates: &[&Project]) -> Option<Uuid> {
+        candidates
+            .iter()
+            .min_by_key(|project| (-project.priority, project.submitted))
+            .map(|project| project.uuid)
+    }
+}
+
+/// Share the workers between the projects in proportion to their weights, with each priority
+/// level doubling the share of a project (so every project keeps making progress)
+#[derive(Default)]
+struct FairShare {
+    /// The number of frames assigned to each project, divided by its share
+    usage: HashMap<Uuid, f64>,
+}
+
+impl FairShare {
+    /// Get the relative share of the workers a project should get
+    fn share(project: &Project) -> f64 {
+        f64::from(project.weight) * 2f64.powi(project.priority)
+    }
+}
+
+impl SchedulingPolicy for FairShare {
+    fn next_project(&mut self, candidates: &[&Project]) -> Option<Uuid> {
+        // Projects that have not been seen before start level with the least-served candidate,
+        // so that they do not get a burst of frames to make up for lost time
+        let start = candidates
+            .iter()
+            .filter_map(|project| self.usage.get(&project.uuid))
+            .copied()
+            .fold(None, |min: Option<f64>, usage| Some(min.map_or(usage, |min| min.min(usage))))
+            .unwrap_or(0.0);
+        for project in candidates {
+            self.usage.entry(project.uuid).or_insert(start);
+        }
+        // Choose the project that has received the smallest part of its share
+        let usage = &self.usage;
+        candidates
+            .iter()
+            .min_by(|a, b| usage[&a.uuid].partial_cmp(&usage[&b.uuid]).unwrap())
+            .map(|project| project.uuid)
+    }
+
+    fn frame_assigned(&mut self, project: &Project) {
+        *self.usage.entry(project.uuid).or_default() += 1.0 / Self::share(project);
+    }
+
+    fn project_removed(&mut self, project_uuid: &Uuid) {
+        self.usage.remove(project_uuid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::project::tests::settings;
+    use std::time::{Duration, SystemTime};
+
+    /// Create a project submitted a number of seconds after the epoch
+    fn project(submitted: u64, priority: i32, weight: u32) -> Project {
+        let mut project = Project::new(settings("1-100"));
+        project.submitted = SystemTime::UNIX_EPOCH + Duration::from_secs(submitted);
+        project.priority = priority;
+        project.weight = weight;
+        project
+    }
+
+    /// Assign a number of frames using a policy and count the frames assigned to each project
+    fn assign(policy: &mut dyn SchedulingPolicy, projects: &[&Project], frames: usize) -> Vec<u32> {
+        let mut counts = vec![0; projects.len()];
+        for _ in 0..frames {
+            let uuid = policy.next_project(projects).unwrap();
+            let index = projects.iter().position(|project| project.uuid == uuid).unwrap();
+            policy.frame_assigned(projects[index]);
+            counts[index] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn no_candidates() {
+        for kind in &[PolicyKind::RoundRobin, PolicyKind::Fifo, PolicyKind::FairShare] {
+            assert_eq!(kind.create().next_project(&[]), None);
+        }
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        // Priorities and weights are ignored
+        let (a, b, c) = (project(0, 0, 1), project(1, 5, 1), project(2, 0, 10));
+        let mut policy = RoundRobin::default();
+        let order: Vec<_> = (0..6)
+            .map(|_| {
+                let uuid = policy.next_project(&[&a, &b, &c]).unwrap();
+                policy.frame_assigned([&a, &b, &c].iter().find(|p| p.uuid == uuid).unwrap());
+                uuid
+            })
+            .collect();
+        assert_eq!(order, vec![a.uuid, b.uuid, c.uuid, a.uuid, b.uuid, c.uuid]);
+        // A new project gets the next turn
+        let d = project(3, 0, 1);
+        assert_eq!(policy.next_project(&[&a, &b, &c, &d]), Some(d.uuid));
+    }
+
+    #[test]
+    fn fifo_orders_by_priority_then_submission() {
+        let (a, b, c) = (project(2, 0, 1), project(1, 0, 1), project(3, 1, 1));
+        let mut policy = Fifo;
+        assert_eq!(policy.next_project(&[&a, &b, &c]), Some(c.uuid));
+        assert_eq!(policy.next_project(&[&a, &b]), Some(b.uuid));
+        // Assigning frames does not change the order
+        assert_eq!(assign(&mut policy, &[&a, &b], 10), vec![0, 10]);
+        // Negative priorities come last
+        let d = project(0, -1, 1);
+        assert_eq!(policy.next_project(&[&d, &a]), Some(a.uuid));
+    }
+
+    #[test]
+    fn fair_share_weights_and_priorities() {
+        // The shares are 1, 3 and 1 * 2^1
+        let (a, b, c) = (project(0, 0, 1), project(1, 0, 3), project(2, 1, 1));
+        let mut policy = FairShare::default();
+        assert_eq!(assign(&mut policy, &[&a, &b, &c], 60), vec![10, 30, 20]);
+        // Lower priorities still get a share
+        let d = project(3, -2, 4);
+        let mut policy = FairShare::default();
+        assert_eq!(assign(&mut policy, &[&a, &d], 20), vec![10, 10]);
+    }
+
+    #[test]
+    fn fair_share_new_project_starts_level() {
+        let (a, b) = (project(0, 0, 1), project(1, 0, 1));
+        let mut policy = FairShare::default();
+        assert_eq!(assign(&mut policy, &[&a], 10), vec![10]);
+        // The new project does not get to make up for the frames it was not queued for
+        assert_eq!(assign(&mut policy, &[&a, &b], 10), vec![5, 5]);
+        // A removed project is forgotten
+        policy.project_removed(&a.uuid);
+        assert!(!policy.usage.contains_key(&a.uuid));
+    }
+}
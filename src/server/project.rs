@@ -1,43 +1,157 @@
-use crate::common::render_task::{FileExt, Frame};
-use std::collections::{HashSet, VecDeque};
-use std::fmt;
+use crate::common::file::get_frame_file_name;
+use crate::common::frame_spec::FrameSpec;
+use crate::common::output::OutputOptions;
+use crate::common::overrides::RenderOverrides;
+use crate::common::render_task::{FileExt, Frame, RenderFailure, RenderProgress};
+use crate::common::retry::{FrameAttempt, RetryPolicy};
+use crate::common::settings::{FrameOrder, ProjectSettings};
+use crate::common::status::{AssignedFrame, ProjectStatus};
+use crate::common::video::{VideoSettings, VideoState};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use std::{fmt, fs};
 use uuid::Uuid;
 
 /// A project submitted to the server
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub(super) struct Project {
     pub uuid: Uuid,
     pub name: String,
     pub output_ext: FileExt,
+    #[serde(default)]
+    pub output_options: OutputOptions,
+    /// The frames the project was submitted with (missing for projects saved by older versions)
+    #[serde(default)]
+    pub frame_spec: FrameSpec,
+    #[serde(default)]
+    pub frame_order: FrameOrder,
+    #[serde(default)]
+    pub overrides: RenderOverrides,
+    /// The project whose project file is rendered, if it is not the project's own upload
+    #[serde(default)]
+    pub shared_file: Option<Uuid>,
+    /// The video to encode the frames into once every frame has been rendered
+    #[serde(default)]
+    pub video: Option<VideoSettings>,
+    #[serde(default)]
+    pub video_state: Option<VideoState>,
     pub waiting_frames: VecDeque<Frame>,
-    pub assigned_frames: HashSet<Frame>,
+    pub assigned_frames: BTreeMap<Frame, Option<String>>,
     pub completed_frames: VecDeque<Frame>,
     pub failed_frames: VecDeque<Frame>,
+    #[serde(default)]
+    pub delayed_frames: VecDeque<(Frame, SystemTime)>,
+    #[serde(default)]
+    pub attempts: BTreeMap<Frame, Vec<FrameAttempt>>,
+    /// The retry policy (missing for projects saved by older versions, which retried nothing)
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    pub cancelled: bool,
+    #[serde(default)]
+    pub paused: bool,
+    pub submitted: SystemTime,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// The maximum number of consecutive frames rendered by a single task
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: Frame,
+    /// The frames that workers are rendering (not saved, since the renders are lost on restart)
+    #[serde(skip)]
+    pub rendering: BTreeMap<Frame, RenderingFrame>,
+    /// The total time spent rendering the frames that were completed
+    #[serde(default)]
+    pub render_time: Duration,
+    /// The number of completed frames included in the render time
+    #[serde(default)]
+    pub num_timed: Frame,
 }
 
-impl Project {
-    /// Create a new project with the specified name
-    pub(super) fn new(
-        name: String,
-        output_ext: FileExt,
-        start_frame: Frame,
-        end_frame: Frame,
-    ) -> Project {
-        assert!(start_frame <= end_frame);
+/// The weight of projects saved before weights were added
+fn default_weight() -> u32 {
+    1
+}
+
+/// The chunk size of projects saved before chunks were added
+fn default_chunk_size() -> Frame {
+    1
+}
+
+/// A frame that a worker is rendering
+#[derive(Debug)]
+pub(super) struct RenderingFrame {
+    pub started: SystemTime,
+    pub progress: RenderProgress,
+}
 
-        let waiting_frames = (start_frame..=end_frame).collect();
+impl Project {
+    /// Create a new project with the specified settings
+    pub(super) fn new(settings: ProjectSettings) -> Project {
+        let frame_spec = settings.frame_spec().expect("settings should have been validated");
+        let waiting_frames = match settings.frame_order {
+            FrameOrder::Sequential => frame_spec.frames().into_iter().collect(),
+            FrameOrder::Progressive => {
+                // Order the chunks rather than the frames, so that chunks are not broken up
+                // Chunks only hold consecutive frames, the same as the tasks made by chunk_end
+                let frames = frame_spec.frames();
+                let chunks: Vec<&[Frame]> = frames
+                    .chunk_by(|&frame, &next_frame| next_frame == frame + 1)
+                    .flat_map(|run| run.chunks(settings.chunk_size as usize))
+                    .collect();
+                progressive_order(&chunks).into_iter().flatten().copied().collect()
+            }
+        };
 
         Project {
             uuid: Uuid::new_v4(),
-            name,
-            output_ext,
+            name: settings.name,
+            output_ext: settings.output_ext,
+            output_options: settings.output_options,
+            frame_spec,
+            frame_order: settings.frame_order,
+            overrides: settings.overrides,
+            shared_file: None,
+            video: settings.video,
+            video_state: None,
             waiting_frames,
-            assigned_frames: HashSet::new(),
+            assigned_frames: BTreeMap::new(),
             completed_frames: VecDeque::new(),
             failed_frames: VecDeque::new(),
+            delayed_frames: VecDeque::new(),
+            attempts: BTreeMap::new(),
+            retry_policy: settings.retry_policy,
+            cancelled: false,
+            paused: false,
+            submitted: SystemTime::now(),
+            priority: settings.priority,
+            weight: settings.weight,
+            chunk_size: settings.chunk_size,
+            rendering: BTreeMap::new(),
+            render_time: Duration::default(),
+            num_timed: 0,
         }
     }
 
+    /// Get the project whose project file is rendered
+    pub(super) fn file_uuid(&self) -> Uuid {
+        self.shared_file.unwrap_or(self.uuid)
+    }
+
+    /// Check whether a project reference (a UUID, UUID prefix, or name) refers to this project
+    pub(super) fn matches(&self, reference: &str) -> bool {
+        self.uuid.to_string().starts_with(&reference.to_lowercase()) || self.name == reference
+    }
+
+    /// Check whether frames of the project can be assigned (it is not paused or cancelled)
+    pub(super) fn active(&self) -> bool {
+        !self.cancelled && !self.paused
+    }
+
     /// Check whether all of the frames have been rendered
     pub(super) fn complete(&self) -> bool {
         self.num_completed() == self.num_frames()
@@ -50,7 +164,11 @@ impl Project {
 
     /// Get the total number of frames
     pub(super) fn num_frames(&self) -> Frame {
-        self.num_waiting() + self.num_assigned() + self.num_completed() + self.num_failed()
+        self.num_waiting()
+            + self.num_delayed()
+            + self.num_assigned()
+            + self.num_completed()
+            + self.num_failed()
     }
 
     /// Get the number of waiting frames
@@ -58,6 +176,11 @@ impl Project {
         self.waiting_frames.len() as Frame
     }
 
+    /// Get the number of frames waiting for their retry delay to pass
+    pub(super) fn num_delayed(&self) -> Frame {
+        self.delayed_frames.len() as Frame
+    }
+
     /// Get the number of assigned frames
     pub(super) fn num_assigned(&self) -> Frame {
         self.assigned_frames.len() as Frame
@@ -73,10 +196,236 @@ impl Project {
         self.failed_frames.len() as Frame
     }
 
-    /// Move all of the failed frames back to the waiting queue
+    /// Record that a worker has started rendering the frames of a task
+    pub(super) fn start_rendering(&mut self, frames: RangeInclusive<Frame>) {
+        let started = SystemTime::now();
+        for frame in frames {
+            let progress = RenderProgress::default();
+            self.rendering.insert(frame, RenderingFrame { started, progress });
+        }
+    }
+
+    /// Stop tracking the render of the frames of a task, adding the render time of the frames
+    /// that completed to the total (the frames of a task share its render time equally)
+    pub(super) fn stop_rendering(&mut self, frames: RangeInclusive<Frame>, num_completed: Frame) {
+        let num_frames = frames.clone().count() as u32;
+        let mut started = None;
+        for frame in frames {
+            if let Some(rendering) = self.rendering.remove(&frame) {
+                started = Some(rendering.started);
+            }
+        }
+        if let Some(started) = started.filter(|_| num_completed > 0) {
+            let elapsed = started.elapsed().unwrap_or_default();
+            self.render_time += elapsed / num_frames * num_completed;
+            self.num_timed += num_completed;
+        }
+    }
+
+    /// Get the last frame of a task starting at a waiting frame, extending it with the following
+    /// waiting frames up to the chunk size
+    /// Frames that failed before are rendered on their own, so they can go to other workers
+    pub(super) fn chunk_end(&self, start_frame: Frame) -> Frame {
+        let mut end_frame = start_frame;
+        if self.attempts.contains_key(&start_frame) {
+            return end_frame;
+        }
+        while end_frame - start_frame + 1 < self.chunk_size
+            && self.waiting_frames.contains(&(end_frame + 1))
+            && !self.attempts.contains_key(&(end_frame + 1))
+        {
+            end_frame += 1;
+        }
+        end_frame
+    }
+
+    /// Estimate how long the remaining frames will take to render with the specified number of
+    /// workers, based on the average render time of the completed frames
+    pub(super) fn eta(&self, num_workers: usize) -> Option<Duration> {
+        if self.num_timed == 0 || num_workers == 0 {
+            return None;
+        }
+        let average = self.render_time / self.num_timed;
+        // Time left for the frames that are being rendered
+        let rendering: Duration = self
+            .rendering
+            .values()
+            .map(|rendering| match (rendering.progress.remaining, rendering.progress.fraction) {
+                (Some(remaining), _) => remaining,
+                (None, Some(fraction)) => average.mul_f32(1.0 - fraction),
+                (None, None) => average,
+            })
+            .sum();
+        // Time for the frames that have not been started
+        let num_queued = self.num_assigned() - self.rendering.len() as Frame;
+        let waiting = average * (self.num_waiting() + self.num_delayed() + num_queued);
+        Some((rendering + waiting) / num_workers as u32)
+    }
+
+    /// Move all of the failed frames back to the waiting queue with a fresh set of attempts
     pub(super) fn retry_failed(&mut self) {
+        for frame in &self.failed_frames {
+            self.attempts.remove(frame);
+        }
         self.waiting_frames.append(&mut self.failed_frames);
     }
+
+    /// Record a failed attempt at rendering a frame, then either schedule it to be retried or
+    /// move it to the failed queue if it has run out of attempts
+    /// Returns whether the frame will be retried
+    pub(super) fn record_failure(
+        &mut self,
+        frame: Frame,
+        worker: Option<String>,
+        failure: RenderFailure,
+    ) -> bool {
+        let attempts = self.attempts.entry(frame).or_default();
+        attempts.push(FrameAttempt { worker, time: SystemTime::now(), failure: Some(failure) });
+        if attempts.len() as u32 >= self.retry_policy.max_attempts {
+            self.failed_frames.push_back(frame);
+            false
+        } else if !self.retry_policy.retry_delay.is_zero() {
+            let retry_time = SystemTime::now() + self.retry_policy.retry_delay;
+            self.delayed_frames.push_back((frame, retry_time));
+            true
+        } else {
+            self.waiting_frames.push_back(frame);
+            true
+        }
+    }
+
+    /// Check whether a worker has already failed to render a frame
+    pub(super) fn failed_on(&self, frame: Frame, worker: &str) -> bool {
+        self.attempts
+            .get(&frame)
+            .into_iter()
+            .flatten()
+            .any(|attempt| attempt.worker.as_deref() == Some(worker))
+    }
+
+    /// Move the delayed frames whose retry time has passed to the waiting queue
+    /// Returns whether any frames were moved
+    pub(super) fn release_delayed_frames(&mut self, now: SystemTime) -> bool {
+        let (released, delayed): (VecDeque<_>, VecDeque<_>) =
+            self.delayed_frames.drain(..).partition(|&(_, retry_time)| retry_time <= now);
+        self.delayed_frames = delayed;
+        self.waiting_frames.extend(released.iter().map(|&(frame, _)| frame));
+        !released.is_empty()
+    }
+
+    /// Get the earliest time at which a delayed frame can be retried
+    pub(super) fn next_retry_time(&self) -> Option<SystemTime> {
+        self.delayed_frames.iter().map(|&(_, retry_time)| retry_time).min()
+    }
+
+    /// Restore a project loaded from a previous run of the server
+    /// Assigned frames and completed frames with missing output files are moved back to waiting,
+    /// and the video is encoded again once they are complete
+    pub(super) fn recover(&mut self, project_dir: &Path) {
+        let output_ext = self.output_ext;
+        // Projects saved by older versions cover every frame they were created with
+        if self.frame_spec.is_empty() {
+            self.frame_spec = FrameSpec::from_frames(
+                self.waiting_frames
+                    .iter()
+                    .chain(self.assigned_frames.keys())
+                    .chain(self.completed_frames.iter())
+                    .chain(self.failed_frames.iter())
+                    .chain(self.delayed_frames.iter().map(|(frame, _)| frame))
+                    .copied(),
+            );
+        }
+        // Assigned frames were interrupted, so put them at the front of the waiting queue
+        let assigned_frames = std::mem::take(&mut self.assigned_frames);
+        for &frame in assigned_frames.keys().rev() {
+            // Remove any partially-uploaded output so that it is not resumed
+            let _ = fs::remove_file(project_dir.join(get_frame_file_name(frame, output_ext)));
+            self.waiting_frames.push_front(frame);
+        }
+        // Check that the output of every completed frame is still present
+        let (present, missing): (VecDeque<Frame>, VecDeque<Frame>) = self
+            .completed_frames
+            .drain(..)
+            .partition(|&frame| project_dir.join(get_frame_file_name(frame, output_ext)).is_file());
+        self.completed_frames = present;
+        for frame in missing {
+            warn!("Output of project {} frame {} is missing", &self.uuid, frame);
+            self.waiting_frames.push_back(frame);
+        }
+        if !self.complete() {
+            self.video_state = None;
+        }
+    }
+
+    /// Get a snapshot of the state of the project
+    pub(super) fn status(&self, num_workers: usize) -> ProjectStatus {
+        ProjectStatus {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            output_ext: self.output_ext,
+            output_options: self.output_options.clone(),
+            frame_spec: self.frame_spec.clone(),
+            frame_order: self.frame_order,
+            overrides: self.overrides.clone(),
+            shared_file: self.shared_file,
+            video: self.video.clone(),
+            video_state: self.video_state.clone(),
+            cancelled: self.cancelled,
+            paused: self.paused,
+            priority: self.priority,
+            weight: self.weight,
+            chunk_size: self.chunk_size,
+            num_waiting: self.num_waiting() + self.num_delayed(),
+            num_assigned: self.num_assigned(),
+            num_completed: self.num_completed(),
+            num_failed: self.num_failed(),
+            progress: self.progress(),
+            waiting_frames: self
+                .waiting_frames
+                .iter()
+                .copied()
+                .chain(self.delayed_frames.iter().map(|&(frame, _)| frame))
+                .collect(),
+            assigned_frames: self
+                .assigned_frames
+                .iter()
+                .map(|(&frame, worker)| AssignedFrame {
+                    frame,
+                    worker: worker.clone(),
+                    progress: self
+                        .rendering
+                        .get(&frame)
+                        .map(|rendering| rendering.progress.clone()),
+                })
+                .collect(),
+            completed_frames: self.completed_frames.iter().copied().collect(),
+            failed_frames: self.failed_frames.iter().copied().collect(),
+            attempts: self.attempts.clone(),
+            retry_policy: self.retry_policy,
+            eta: if self.active() { self.eta(num_workers) } else { None },
+        }
+    }
+}
+
+/// Order items coarse to fine: the first and last items, then the middle item, then the items
+/// halfway between those, and so on
+fn progressive_order<T: Copy>(items: &[T]) -> Vec<T> {
+    if items.len() <= 2 {
+        return items.to_vec();
+    }
+    let mut order = vec![items[0], items[items.len() - 1]];
+    // Split each interval at its middle item, breadth first
+    let mut intervals = VecDeque::from(vec![(0, items.len() - 1)]);
+    while let Some((start, end)) = intervals.pop_front() {
+        if end - start < 2 {
+            continue;
+        }
+        let middle = (start + end) / 2;
+        order.push(items[middle]);
+        intervals.push_back((start, middle));
+        intervals.push_back((middle, end));
+    }
+    order
 }
 
 impl fmt::Display for Project {
@@ -84,3 +433,206 @@ impl fmt::Display for Project {
         write!(f, "{}", self.name)
     }
 }
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::common::render_task::RenderFailureKind;
+
+    /// Get the settings of a project that renders the specified frames
+    pub(in crate::server) fn settings(frames: &str) -> ProjectSettings {
+        ProjectSettings {
+            name: String::from("test"),
+            output_ext: FileExt::PNG,
+            output_options: OutputOptions::default(),
+            frames: frames.to_string(),
+            retry_policy: RetryPolicy { max_attempts: 3, retry_delay: Duration::default() },
+            priority: 0,
+            weight: 1,
+            chunk_size: 1,
+            frame_order: FrameOrder::Sequential,
+            overrides: RenderOverrides::default(),
+            shared_file: None,
+            video: None,
+        }
+    }
+
+    #[test]
+    fn progressive_order_is_permutation() {
+        for len in 0..100 {
+            let items: Vec<u32> = (0..len).collect();
+            let mut order = progressive_order(&items);
+            order.sort_unstable();
+            assert_eq!(order, items);
+        }
+    }
+
+    #[test]
+    fn progressive_order_starts_coarse() {
+        let items: Vec<u32> = (0..=10).collect();
+        let order = progressive_order(&items);
+        assert_eq!(order[..3], [0, 10, 5]);
+        // Then the items halfway between those
+        assert_eq!(order[3..5], [2, 7]);
+    }
+
+    #[test]
+    fn progressive_order_of_few_items() {
+        assert_eq!(progressive_order::<u32>(&[]), Vec::<u32>::new());
+        assert_eq!(progressive_order(&[4]), vec![4]);
+        assert_eq!(progressive_order(&[4, 5]), vec![4, 5]);
+        assert_eq!(progressive_order(&[4, 5, 6]), vec![4, 6, 5]);
+    }
+
+    #[test]
+    fn progressive_order_of_stepped_spec() {
+        let frames = "1-100x10".parse::<FrameSpec>().unwrap().frames();
+        let order = progressive_order(&frames);
+        assert_eq!(order[..3], [1, 91, 41]);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, frames);
+    }
+
+    #[test]
+    fn chunk_end_stops_at_gaps_and_steps() {
+        let project = Project::new(ProjectSettings { chunk_size: 3, ..settings("1-4,6-7") });
+        assert_eq!(project.chunk_end(1), 3);
+        assert_eq!(project.chunk_end(4), 4);
+        assert_eq!(project.chunk_end(6), 7);
+        // Stepped frames are rejected with a chunk size above 1, but are never merged anyway
+        let project = Project::new(ProjectSettings { chunk_size: 3, ..settings("1-9x2") });
+        assert_eq!(project.chunk_end(1), 1);
+        assert_eq!(project.chunk_end(9), 9);
+    }
+
+    #[test]
+    fn chunk_end_skips_failed_frames() {
+        let mut project = Project::new(ProjectSettings { chunk_size: 3, ..settings("1-6") });
+        project.attempts.insert(2, Vec::new());
+        assert_eq!(project.chunk_end(1), 1);
+        assert_eq!(project.chunk_end(2), 2);
+        assert_eq!(project.chunk_end(3), 5);
+    }
+
+    #[test]
+    fn progressive_chunks_stop_at_gaps() {
+        let project = Project::new(ProjectSettings {
+            chunk_size: 3,
+            frame_order: FrameOrder::Progressive,
+            ..settings("1-4,6-7,10")
+        });
+        // The chunks are [1, 2, 3], [4], [6, 7] and [10]
+        assert_eq!(project.waiting_frames, vec![1, 2, 3, 10, 4, 6, 7]);
+        assert_eq!(project.chunk_end(6), 7);
+    }
+
+    #[test]
+    fn recover_moves_assigned_frames_to_waiting() {
+        let project_dir = std::env::temp_dir().join(format!("tinyrf-project-{}", Uuid::new_v4()));
+        fs::create_dir(&project_dir).unwrap();
+        let mut project = Project::new(settings("1-6"));
+        project.waiting_frames = VecDeque::from(vec![5, 6]);
+        project.completed_frames = VecDeque::from(vec![1, 2]);
+        project.assigned_frames.insert(3, Some(String::from("worker")));
+        project.assigned_frames.insert(4, None);
+        // The output of frame 1 was kept, and frame 3 was partially uploaded
+        fs::write(project_dir.join(get_frame_file_name(1, FileExt::PNG)), "").unwrap();
+        fs::write(project_dir.join(get_frame_file_name(3, FileExt::PNG)), "").unwrap();
+        project.recover(&project_dir);
+        let partial_output = project_dir.join(get_frame_file_name(3, FileExt::PNG)).is_file();
+        fs::remove_dir_all(&project_dir).unwrap();
+        assert!(!partial_output);
+        assert!(project.assigned_frames.is_empty());
+        assert_eq!(project.waiting_frames, vec![3, 4, 5, 6, 2]);
+        assert_eq!(project.completed_frames, vec![1]);
+        assert_eq!(project.num_frames(), 6);
+    }
+
+    fn failure() -> RenderFailure {
+        RenderFailure {
+            kind: RenderFailureKind::ExitStatus,
+            message: String::from("process exited with error"),
+            exit_code: Some(1),
+            signal: None,
+            stderr_tail: Vec::new(),
+        }
+    }
+
+    /// Take a waiting frame and record a failed attempt at rendering it on a worker
+    fn fail(project: &mut Project, frame: Frame, worker: &str) -> bool {
+        let index = project.waiting_frames.iter().position(|&waiting| waiting == frame).unwrap();
+        project.waiting_frames.remove(index);
+        project.record_failure(frame, Some(worker.to_string()), failure())
+    }
+
+    #[test]
+    fn retry_until_max_attempts() {
+        let mut project = Project::new(settings("1-2"));
+        assert!(fail(&mut project, 1, "a"));
+        assert!(fail(&mut project, 1, "b"));
+        assert_eq!(project.waiting_frames, vec![2, 1]);
+        assert!(project.failed_frames.is_empty());
+        // The third attempt is the last one
+        assert!(!fail(&mut project, 1, "c"));
+        assert_eq!(project.waiting_frames, vec![2]);
+        assert_eq!(project.failed_frames, vec![1]);
+        assert_eq!(project.num_frames(), 2);
+        // Every attempt is kept, along with the worker that made it
+        let workers: Vec<_> =
+            project.attempts[&1].iter().map(|attempt| attempt.worker.as_deref()).collect();
+        assert_eq!(workers, vec![Some("a"), Some("b"), Some("c")]);
+        assert!(project.attempts[&1].iter().all(|attempt| attempt.failure.is_some()));
+        assert!(project.failed_on(1, "b"));
+        assert!(!project.failed_on(1, "d"));
+        assert!(!project.failed_on(2, "a"));
+    }
+
+    #[test]
+    fn retry_failed_resets_attempts() {
+        let mut project = Project::new(ProjectSettings {
+            retry_policy: RetryPolicy { max_attempts: 1, retry_delay: Duration::default() },
+            ..settings("1")
+        });
+        assert!(!fail(&mut project, 1, "a"));
+        project.retry_failed();
+        assert_eq!(project.waiting_frames, vec![1]);
+        assert!(project.failed_frames.is_empty());
+        assert!(!project.attempts.contains_key(&1));
+    }
+
+    #[test]
+    fn delay_retries() {
+        let retry_delay = Duration::from_secs(60);
+        let mut project = Project::new(ProjectSettings {
+            retry_policy: RetryPolicy { max_attempts: 3, retry_delay },
+            ..settings("1-2")
+        });
+        let before = SystemTime::now();
+        assert!(fail(&mut project, 1, "a"));
+        assert_eq!(project.waiting_frames, vec![2]);
+        assert_eq!(project.num_delayed(), 1);
+        assert_eq!(project.num_frames(), 2);
+        let retry_time = project.next_retry_time().unwrap();
+        assert!(retry_time >= before + retry_delay);
+        // The frame stays delayed until the retry time
+        assert!(!project.release_delayed_frames(retry_time - Duration::from_secs(1)));
+        assert_eq!(project.num_delayed(), 1);
+        assert!(project.release_delayed_frames(retry_time));
+        assert_eq!(project.waiting_frames, vec![2, 1]);
+        assert_eq!(project.next_retry_time(), None);
+    }
+
+    #[test]
+    fn load_project_without_retry_state() {
+        // Projects saved before retries were added have no retry policy, attempts or delayed
+        // frames
+        let mut json = serde_json::to_value(Project::new(settings("1-3"))).unwrap();
+        for field in &["delayed_frames", "attempts", "retry_policy"] {
+            json.as_object_mut().unwrap().remove(*field);
+        }
+        let project: Project = serde_json::from_value(json).unwrap();
+        assert_eq!(project.retry_policy.max_attempts, 1);
+        assert!(project.delayed_frames.is_empty() && project.attempts.is_empty());
+    }
+}
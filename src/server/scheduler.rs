@@ -1,10 +1,21 @@
-use crate::common::render_task::{RenderTask, RenderTaskResult};
+use crate::common::file::{get_project_dir, get_project_file};
+use crate::common::render_task::{
+    Frame, RenderFailure, RenderProgress, RenderTask, RenderTaskResult,
+};
+use crate::common::status::{ProjectStatus, WorkerStatus};
+use crate::common::video::VideoState;
+use crate::server::events::Event;
+use crate::server::policy::SchedulingPolicy;
 use crate::server::project::Project;
+use crate::server::state::save_projects;
+use crate::server::video::encode_video;
 use crossbeam_channel::internal::SelectHandle;
 use crossbeam_channel::{Receiver, Select, Sender};
-use log::{debug, error, info};
-use std::collections::{HashMap, VecDeque};
-use std::thread;
+use log::{debug, error, info, warn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
 use uuid::Uuid;
 
 /// The maximum number of tasks that can be waiting in the render channel
@@ -15,30 +26,145 @@ const RENDER_CHANNEL_SIZE: usize = 1;
 #[derive(Debug)]
 pub(super) struct SchedulerRenderMessage(pub RenderTask);
 
-/// A message sent to the scheduler with the result of a render
+/// A message sent by the scheduler to a single worker connection
 #[derive(Debug)]
-pub(super) struct SchedulerResultMessage(pub RenderTask, pub RenderTaskResult);
+pub(super) enum SchedulerControlMessage {
+    // Render a frame that should not go to the workers that already failed it
+    Render(RenderTask),
+    // Stop or resume taking tasks from the render channel
+    SetQuarantined(bool),
+    // Stop rendering a task
+    Abort(RenderTask),
+}
+
+/// The identity of a worker connection
+/// Failures are tracked by name, which stays the same when a worker reconnects
+#[derive(Debug, Clone)]
+pub(super) struct WorkerId {
+    pub connection: Uuid,
+    pub name: String,
+}
+
+/// A message sent to the scheduler with the progress or result of a render
+#[derive(Debug)]
+pub(super) enum SchedulerResultMessage {
+    // A worker has connected
+    Connected(WorkerId, Sender<SchedulerControlMessage>),
+    // A worker has disconnected
+    Disconnected(WorkerId),
+    // A worker has picked up a render task
+    Started(RenderTask, WorkerId),
+    // A worker has reported the progress of a render task
+    Progress(RenderTask, RenderProgress),
+    // A render task finished with result
+    Finished(RenderTask, WorkerId, RenderTaskResult),
+    // A render task was aborted
+    Aborted(RenderTask, WorkerId),
+    // The worker stopped responding while rendering a frame of a render task
+    Expired(RenderTask, WorkerId, Frame, RenderFailure),
+    // The worker was lost before a render task finished
+    Lost(RenderTask, WorkerId),
+    // The video of a project was encoded, or failed with an error
+    VideoEncoded(Uuid, Result<(), String>),
+}
 
 /// A message sent to the scheduler with a project management task
 #[derive(Debug)]
 pub(super) enum SchedulerManageMessage {
     // Add a project to the queue
-    AddProject(Project),
+    AddProject(Box<Project>),
     // Retry a project's failed frames
     RetryFailed(Uuid),
+    // Stop assigning a project's frames until it is resumed
+    PauseProject(Uuid),
+    // Start assigning a paused project's frames again
+    ResumeProject(Uuid),
+    // Stop assigning a project's frames and abort the frames being rendered
+    CancelProject(Uuid),
+    // Cancel a project and delete it along with its files
+    RemoveProject(Uuid),
+    // Get the status of all of the projects
+    ListProjects(Sender<Vec<ProjectStatus>>),
+    // Get the status of the project a reference refers to
+    FindProject(String, Sender<Result<ProjectStatus, String>>),
+    // Get the status of all of the workers
+    ListWorkers(Sender<Vec<WorkerStatus>>),
+    // Let a quarantined worker take tasks again
+    ReleaseWorker(String),
+}
+
+/// Get the status of all of the projects from the scheduler
+pub(super) fn list_projects(manage_send: &Sender<SchedulerManageMessage>) -> Vec<ProjectStatus> {
+    let (status_send, status_recv) = crossbeam_channel::bounded(1);
+    manage_send.send(SchedulerManageMessage::ListProjects(status_send)).unwrap();
+    status_recv.recv().unwrap()
+}
+
+/// Get the status of all of the workers from the scheduler
+pub(super) fn list_workers(manage_send: &Sender<SchedulerManageMessage>) -> Vec<WorkerStatus> {
+    let (status_send, status_recv) = crossbeam_channel::bounded(1);
+    manage_send.send(SchedulerManageMessage::ListWorkers(status_send)).unwrap();
+    status_recv.recv().unwrap()
+}
+
+/// Find the project a reference (a UUID, UUID prefix, or name) refers to
+pub(super) fn find_project(
+    manage_send: &Sender<SchedulerManageMessage>,
+    reference: &str,
+) -> Result<ProjectStatus, String> {
+    let (status_send, status_recv) = crossbeam_channel::bounded(1);
+    let message = SchedulerManageMessage::FindProject(reference.to_string(), status_send);
+    manage_send.send(message).unwrap();
+    status_recv.recv().unwrap()
+}
+
+/// A worker connection known to the scheduler
+struct WorkerConnection {
+    name: String,
+    control_send: Sender<SchedulerControlMessage>,
+    busy: bool,
+    /// A task sent to the connection that it has not picked up yet
+    pending: Option<RenderTask>,
+    /// The task the connection is rendering
+    task: Option<RenderTask>,
+}
+
+/// The render history of a worker
+#[derive(Default)]
+struct WorkerStats {
+    num_completed: u32,
+    num_failed: u32,
+    consecutive_failures: u32,
+    quarantined: bool,
 }
 
 pub(crate) struct Scheduler {
     projects: HashMap<Uuid, Project>,
     queue: VecDeque<Uuid>,
+    connections: HashMap<Uuid, WorkerConnection>,
+    workers: BTreeMap<String, WorkerStats>,
+    quarantine_after: u32,
+    policy: Box<dyn SchedulingPolicy>,
+    /// Used to report events to the thread that runs the hooks
+    event_send: Sender<Event>,
     render_send: Sender<SchedulerRenderMessage>,
+    /// Used by the threads that encode videos to report back to the scheduler
+    result_send: Sender<SchedulerResultMessage>,
     result_recv: Receiver<SchedulerResultMessage>,
     manage_recv: Receiver<SchedulerManageMessage>,
+    working_dir: PathBuf,
+    state_changed: bool,
 }
 
 impl Scheduler {
-    /// Create a scheduler and start it in a new thread
-    pub(super) fn start() -> (
+    /// Create a scheduler with the specified projects and start it in a new thread
+    pub(super) fn start(
+        projects: Vec<Project>,
+        working_dir: PathBuf,
+        quarantine_after: u32,
+        policy: Box<dyn SchedulingPolicy>,
+        event_send: Sender<Event>,
+    ) -> (
         Receiver<SchedulerRenderMessage>,
         Sender<SchedulerResultMessage>,
         Sender<SchedulerManageMessage>,
@@ -54,11 +180,33 @@ impl Scheduler {
         let mut scheduler = Scheduler {
             projects: HashMap::new(),
             queue: VecDeque::new(),
+            connections: HashMap::new(),
+            workers: BTreeMap::new(),
+            quarantine_after,
+            policy,
+            event_send,
             render_send,
+            result_send: result_send.clone(),
             result_recv,
             manage_recv,
+            working_dir,
+            state_changed: false,
         };
 
+        // Add the projects that still have waiting frames to the queue
+        for project in projects {
+            if project.active() && project.num_waiting() > 0 {
+                scheduler.queue.push_back(project.uuid);
+            }
+            // Start again on videos that were interrupted
+            let encoding = project.video_state == Some(VideoState::Encoding);
+            let project_uuid = project.uuid;
+            scheduler.projects.insert(project.uuid, project);
+            if encoding {
+                scheduler.start_encoding(project_uuid);
+            }
+        }
+
         // Start the scheduler in a new thread
         thread::spawn(move || scheduler.run());
 
@@ -75,19 +223,48 @@ impl Scheduler {
         selector.recv(&manage_recv);
 
         loop {
-            // Send render messages while the queue is not empty and the channel has space
-            while !self.queue.is_empty() && self.render_send.is_ready() {
-                // Get the first project in the queue
-                let project_uuid = self.queue.pop_front().unwrap();
-                // Assign the first waiting frame and send a render message
-                self.assign_first_waiting_frame(&project_uuid);
-                // Move the project back into the queue if it still has waiting frames
-                if self.projects.get(&project_uuid).unwrap().num_waiting() > 0 {
-                    self.queue.push_back(project_uuid)
+            // Send frames that failed before directly to idle workers that have not failed them
+            self.assign_retried_frames();
+            // Send render messages while the channel has space and a project has a frame to send
+            while self.render_send.is_ready() {
+                // Let the policy choose between the projects with a frame that any worker can render
+                let candidates: Vec<Uuid> = self
+                    .queue
+                    .iter()
+                    .copied()
+                    .filter(|uuid| self.next_waiting_frame(&self.projects[uuid]).is_some())
+                    .collect();
+                let projects = &self.projects;
+                let candidates: Vec<&Project> =
+                    candidates.iter().map(|uuid| &projects[uuid]).collect();
+                let project_uuid = match self.policy.next_project(&candidates) {
+                    Some(project_uuid) => project_uuid,
+                    None => break,
+                };
+                // Assign the first frame that any worker can render (along with the frames after
+                // it, up to the chunk size) and send a render message
+                let project = &self.projects[&project_uuid];
+                let start_frame = self.next_waiting_frame(project).unwrap();
+                let end_frame = project.chunk_end(start_frame);
+                let render_task = self.assign_frames(&project_uuid, start_frame, end_frame);
+                self.render_send.send(SchedulerRenderMessage(render_task)).unwrap();
+                self.policy.frame_assigned(&self.projects[&project_uuid]);
+            }
+            // Remove the projects that have no waiting frames left from the queue
+            let projects = &self.projects;
+            self.queue.retain(|uuid| projects[uuid].num_waiting() > 0);
+            // Block until there are messages or a delayed frame can be retried
+            match self.next_retry_time() {
+                Some(retry_time) => {
+                    let timeout = retry_time.duration_since(SystemTime::now()).unwrap_or_default();
+                    let _ = selector.ready_timeout(timeout);
+                }
+                None => {
+                    let _ = selector.ready();
                 }
             }
-            // Block until there are messages
-            let _ = selector.ready();
+            // Move delayed frames that can be retried back to the waiting queue
+            self.release_delayed_frames();
             // Handle result messages
             while let Ok(message) = self.result_recv.try_recv() {
                 self.handle_result_msg(message);
@@ -96,86 +273,683 @@ impl Scheduler {
             while let Ok(message) = self.manage_recv.try_recv() {
                 self.handle_manage_msg(message);
             }
+            // Save the state if any of the projects changed
+            if self.state_changed {
+                self.save_state();
+            }
         }
     }
 
-    /// Assign the first waiting frame of a project and send a render message
-    fn assign_first_waiting_frame(&mut self, project_uuid: &Uuid) {
-        // Get the first waiting frame of the project
-        let project = self.projects.get_mut(&project_uuid).unwrap();
-        let frame = project.waiting_frames.pop_front().unwrap();
-        // Move the frame to the assigned queue
-        debug!("Moving project {} frame {} to the ASSIGNED queue", &project.uuid, frame);
-        assert!(project.assigned_frames.insert(frame));
-        // Send a render message
-        let message = SchedulerRenderMessage(RenderTask {
+    /// Get the earliest time at which a delayed frame of any project can be retried
+    fn next_retry_time(&self) -> Option<SystemTime> {
+        self.projects.values().filter_map(Project::next_retry_time).min()
+    }
+
+    /// Move the delayed frames of every project whose retry time has passed to the waiting queue
+    fn release_delayed_frames(&mut self) {
+        let now = SystemTime::now();
+        let mut released = Vec::new();
+        for project in self.projects.values_mut() {
+            if project.release_delayed_frames(now) {
+                debug!("Retrying delayed frames of project {}", &project.uuid);
+                released.push(project.uuid);
+            }
+        }
+        for project_uuid in released {
+            self.state_changed = true;
+            self.queue_project(project_uuid);
+        }
+    }
+
+    /// Add a project to the queue if it has waiting frames, has not been paused or cancelled, and
+    /// is not already present
+    fn queue_project(&mut self, project_uuid: Uuid) {
+        let project = self.projects.get(&project_uuid).unwrap();
+        if project.active() && project.num_waiting() > 0 && !self.queue.contains(&project_uuid) {
+            self.queue.push_back(project_uuid);
+        }
+    }
+
+    /// Save the state of all of the projects to the working directory
+    fn save_state(&mut self) {
+        match save_projects(&self.working_dir, self.projects.values()) {
+            Ok(()) => self.state_changed = false,
+            Err(error) => error!("Error saving state: {}", error),
+        }
+    }
+
+    /// Check whether a worker is connected that can render a frame without having failed it
+    fn has_other_worker(&self, project: &Project, frame: Frame) -> bool {
+        self.connections.values().any(|connection| {
+            !self.workers[&connection.name].quarantined
+                && !project.failed_on(frame, &connection.name)
+        })
+    }
+
+    /// Get the first waiting frame of a project that any worker can render
+    /// Frames that failed before are held back for other workers while there are any
+    fn next_waiting_frame(&self, project: &Project) -> Option<Frame> {
+        project.waiting_frames.iter().copied().find(|&frame| {
+            !project.attempts.contains_key(&frame) || !self.has_other_worker(project, frame)
+        })
+    }
+
+    /// Send waiting frames that failed before to idle workers that have not failed them
+    fn assign_retried_frames(&mut self) {
+        for project_uuid in self.queue.clone() {
+            let project = self.projects.get(&project_uuid).unwrap();
+            // Pair each retried frame with an idle worker that can render it
+            let mut assignments = Vec::new();
+            for &frame in project.waiting_frames.iter() {
+                if !project.attempts.contains_key(&frame) {
+                    continue;
+                }
+                let connection = self.connections.iter().find(|(uuid, connection)| {
+                    !connection.busy
+                        && !self.workers[&connection.name].quarantined
+                        && !project.failed_on(frame, &connection.name)
+                        && !assignments.iter().any(|(_, assigned)| assigned == *uuid)
+                });
+                if let Some((connection_uuid, _)) = connection {
+                    assignments.push((frame, *connection_uuid));
+                }
+            }
+            // Send the frames to the workers
+            for (frame, connection_uuid) in assignments {
+                let render_task = self.assign_frames(&project_uuid, frame, frame);
+                self.policy.frame_assigned(&self.projects[&project_uuid]);
+                let connection = self.connections.get_mut(&connection_uuid).unwrap();
+                debug!("Sending project {} frame {} to {}", &project_uuid, frame, &connection.name);
+                connection.busy = true;
+                connection.pending = Some(render_task.clone());
+                let message = SchedulerControlMessage::Render(render_task);
+                // The frame is recovered when the disconnect message arrives
+                let _ = connection.control_send.send(message);
+            }
+        }
+    }
+
+    /// Move a range of waiting frames of a project to the assigned queue and create their render
+    /// task
+    fn assign_frames(
+        &mut self,
+        project_uuid: &Uuid,
+        start_frame: Frame,
+        end_frame: Frame,
+    ) -> RenderTask {
+        let project = self.projects.get_mut(project_uuid).unwrap();
+        for frame in start_frame..=end_frame {
+            // Remove the frame from the waiting queue
+            let index =
+                project.waiting_frames.iter().position(|&waiting| waiting == frame).unwrap();
+            project.waiting_frames.remove(index);
+            // Move the frame to the assigned queue
+            debug!("Moving project {} frame {} to the ASSIGNED queue", &project.uuid, frame);
+            assert!(project.assigned_frames.insert(frame, None).is_none());
+        }
+        RenderTask {
             project_uuid: project.uuid,
             project_name: project.name.clone(),
-            frame,
+            file_uuid: project.file_uuid(),
+            start_frame,
+            end_frame,
             output_ext: project.output_ext,
-        });
-        self.render_send.send(message).unwrap();
+            output_options: project.output_options.clone(),
+            overrides: project.overrides.clone(),
+        }
     }
 
     /// Handle a result message
     fn handle_result_msg(&mut self, message: SchedulerResultMessage) {
-        let SchedulerResultMessage(render_task, result) = message;
-        // Get the project the frame belongs to
-        let project = self.projects.get_mut(&render_task.project_uuid).unwrap();
-        // Remove the frame from the assigned queue
-        assert!(project.assigned_frames.remove(&render_task.frame));
-        // Handle the result
-        match result {
-            Ok(()) => {
-                // Move the frame to the completed queue
-                debug!(
-                    "Moving project {} frame {} to the COMPLETED queue",
-                    &render_task.project_uuid, render_task.frame
-                );
-                project.completed_frames.push_back(render_task.frame);
-                // Print a message if the project is complete
-                if project.complete() {
-                    info!("Project \"{}\" is finished", project);
+        match message {
+            SchedulerResultMessage::Connected(worker, control_send) => {
+                self.handle_connected(worker, control_send)
+            }
+            SchedulerResultMessage::Disconnected(worker) => self.handle_disconnected(worker),
+            SchedulerResultMessage::Started(render_task, worker) => {
+                self.handle_started(render_task, worker)
+            }
+            SchedulerResultMessage::Progress(render_task, progress) => {
+                self.handle_progress(render_task, progress)
+            }
+            SchedulerResultMessage::Finished(render_task, worker, result) => {
+                self.set_idle(&worker, &render_task);
+                self.handle_finished(render_task, worker, result)
+            }
+            // The frame the worker stopped responding on counts as a failure
+            SchedulerResultMessage::Expired(render_task, worker, frame, failure) => {
+                self.set_idle(&worker, &render_task);
+                let results =
+                    render_task.frames().map(|frame| (frame, Err(failure.clone()))).collect();
+                self.handle_results(render_task, worker, results, Some(frame))
+            }
+            // Aborted frames are not counted as failures
+            SchedulerResultMessage::Aborted(render_task, worker) => {
+                debug!("Render of {} aborted", &render_task);
+                self.set_idle(&worker, &render_task);
+                self.handle_lost(render_task)
+            }
+            SchedulerResultMessage::Lost(render_task, worker) => {
+                self.set_idle(&worker, &render_task);
+                self.handle_lost(render_task)
+            }
+            SchedulerResultMessage::VideoEncoded(project_uuid, result) => {
+                self.handle_encoded(project_uuid, result)
+            }
+        }
+    }
+
+    /// Register a worker connection, letting it know if the worker is quarantined
+    fn handle_connected(
+        &mut self,
+        worker: WorkerId,
+        control_send: Sender<SchedulerControlMessage>,
+    ) {
+        let _ = self.event_send.send(Event::worker_connected(&worker.name));
+        let stats = self.workers.entry(worker.name.clone()).or_default();
+        if stats.quarantined {
+            let _ = control_send.send(SchedulerControlMessage::SetQuarantined(true));
+        }
+        let connection = WorkerConnection {
+            name: worker.name,
+            control_send,
+            busy: false,
+            pending: None,
+            task: None,
+        };
+        self.connections.insert(worker.connection, connection);
+    }
+
+    /// Remove a worker connection, recovering any task it did not pick up
+    fn handle_disconnected(&mut self, worker: WorkerId) {
+        let connection = self.connections.remove(&worker.connection).unwrap();
+        let _ = self.event_send.send(Event::worker_disconnected(&worker.name));
+        if let Some(render_task) = connection.pending {
+            self.handle_lost(render_task);
+        }
+    }
+
+    /// Mark a worker connection as ready for another task, unless it still has a pending task
+    /// A pending task that ended without being started (e.g. because its project was removed) is
+    /// no longer pending
+    fn set_idle(&mut self, worker: &WorkerId, render_task: &RenderTask) {
+        let connection = self.connections.get_mut(&worker.connection).unwrap();
+        if connection.pending.as_ref() == Some(render_task) {
+            connection.pending = None;
+        }
+        connection.busy = connection.pending.is_some();
+        connection.task = None;
+    }
+
+    /// Tell the connections rendering the frames of a project to abort them
+    fn abort_project(&self, project_uuid: &Uuid) {
+        for connection in self.connections.values() {
+            if let Some(render_task) = &connection.task {
+                if render_task.project_uuid == *project_uuid {
+                    debug!("Aborting render of {}", render_task);
+                    let message = SchedulerControlMessage::Abort(render_task.clone());
+                    let _ = connection.control_send.send(message);
                 }
             }
-            Err(()) => {
-                // Move the frame to the failed queue
-                debug!(
-                    "Moving project {} frame {} to the FAILED queue",
-                    &render_task.project_uuid, render_task.frame
-                );
-                project.failed_frames.push_back(render_task.frame);
+        }
+    }
+
+    /// Move the frames of a task whose worker was lost (or whose render was aborted) back to the
+    /// waiting queue
+    fn handle_lost(&mut self, render_task: RenderTask) {
+        let project = match self.projects.get_mut(&render_task.project_uuid) {
+            Some(project) => project,
+            None => return,
+        };
+        project.stop_rendering(render_task.frames(), 0);
+        self.state_changed = true;
+        // Move the frames from the assigned queue to the front of the waiting queue (in order)
+        for frame in render_task.frames().rev() {
+            assert!(project.assigned_frames.remove(&frame).is_some());
+            debug!(
+                "Moving project {} frame {} back to the WAITING queue",
+                &render_task.project_uuid, frame
+            );
+            project.waiting_frames.push_front(frame);
+        }
+        // Add the project to the queue if it is not already present
+        self.queue_project(render_task.project_uuid);
+    }
+
+    /// Record the worker that picked up a render task
+    fn handle_started(&mut self, render_task: RenderTask, worker: WorkerId) {
+        let connection = self.connections.get_mut(&worker.connection).unwrap();
+        connection.busy = true;
+        if connection.pending.as_ref() == Some(&render_task) {
+            connection.pending = None;
+        }
+        connection.task = Some(render_task.clone());
+        let project = match self.projects.get_mut(&render_task.project_uuid) {
+            Some(project) => project,
+            None => {
+                // The project was removed after the task was sent
+                let _ = connection.control_send.send(SchedulerControlMessage::Abort(render_task));
+                return;
             }
+        };
+        debug!("Render of {} started by {}", &render_task, &worker.name);
+        for frame in render_task.frames() {
+            *project.assigned_frames.get_mut(&frame).unwrap() = Some(worker.name.clone());
+        }
+        project.start_rendering(render_task.frames());
+        // Abort the task if the project was cancelled after the task was sent
+        if project.cancelled {
+            self.abort_project(&render_task.project_uuid);
         }
-        // If this was the last assigned frame, check if there are failed frames
-        if project.num_waiting() == 0 && project.num_assigned() == 0 && project.num_failed() > 0 {
+    }
+
+    /// Record the progress of a render task
+    /// The earlier frames of a task have finished rendering by the time a later frame starts
+    fn handle_progress(&mut self, render_task: RenderTask, progress: RenderProgress) {
+        let project = match self.projects.get_mut(&render_task.project_uuid) {
+            Some(project) => project,
+            None => return,
+        };
+        let current = progress.frame.filter(|frame| render_task.frames().contains(frame));
+        let current = current.unwrap_or(render_task.start_frame);
+        for frame in render_task.start_frame..current {
+            if let Some(rendering) = project.rendering.get_mut(&frame) {
+                rendering.progress = RenderProgress {
+                    frame: Some(frame),
+                    fraction: Some(1.0),
+                    remaining: Some(Duration::default()),
+                    peak_memory: None,
+                };
+            }
+        }
+        if let Some(rendering) = project.rendering.get_mut(&current) {
+            rendering.progress = progress;
+        }
+    }
+
+    /// Update the render history of a worker, quarantining it if it failed too many frames in a
+    /// row
+    fn record_worker_result(&mut self, worker: &str, success: bool) {
+        let stats = self.workers.get_mut(worker).unwrap();
+        if success {
+            stats.num_completed += 1;
+            stats.consecutive_failures = 0;
+            return;
+        }
+        stats.num_failed += 1;
+        stats.consecutive_failures += 1;
+        if self.quarantine_after > 0
+            && stats.consecutive_failures >= self.quarantine_after
+            && !stats.quarantined
+        {
+            warn!(
+                "Quarantining worker {} after {} failed frames",
+                worker, stats.consecutive_failures
+            );
+            stats.quarantined = true;
+            self.set_quarantined(worker, true);
+        }
+    }
+
+    /// Let every connection of a worker know whether the worker is quarantined
+    fn set_quarantined(&self, worker: &str, quarantined: bool) {
+        for connection in self.connections.values().filter(|connection| connection.name == worker) {
+            let _ =
+                connection.control_send.send(SchedulerControlMessage::SetQuarantined(quarantined));
+        }
+    }
+
+    /// Handle the result of a render task
+    /// Only the first frame that failed is charged an attempt, since the render process never
+    /// reached the frames after it
+    fn handle_finished(
+        &mut self,
+        render_task: RenderTask,
+        worker: WorkerId,
+        results: RenderTaskResult,
+    ) {
+        let first_failed =
+            results.iter().find(|(_, result)| result.is_err()).map(|(&frame, _)| frame);
+        self.handle_results(render_task, worker, results, first_failed)
+    }
+
+    /// Handle the result of each frame of a render task, charging an attempt to the failed frame
+    /// that caused the failure
+    /// The other failed frames were not rendered, and go back to the waiting queue
+    fn handle_results(
+        &mut self,
+        render_task: RenderTask,
+        worker: WorkerId,
+        results: RenderTaskResult,
+        failed_frame: Option<Frame>,
+    ) {
+        // A task counts as a single result, so that one crash does not count once for every frame
+        self.record_worker_result(&worker.name, results.values().all(Result::is_ok));
+        // Get the project the frames belong to (ignoring the result if it was removed)
+        let project = match self.projects.get_mut(&render_task.project_uuid) {
+            Some(project) => project,
+            None => return,
+        };
+        let num_completed = results.values().filter(|result| result.is_ok()).count() as Frame;
+        project.stop_rendering(render_task.frames(), num_completed);
+        self.state_changed = true;
+        // Handle the result of each frame
+        let mut retrying = false;
+        let mut unrendered = Vec::new();
+        for (frame, result) in results {
+            // Remove the frame from the assigned queue
+            assert!(project.assigned_frames.remove(&frame).is_some());
+            match result {
+                Err(_) if Some(frame) != failed_frame => unrendered.push(frame),
+                Ok(()) => {
+                    // Move the frame to the completed queue
+                    debug!(
+                        "Moving project {} frame {} to the COMPLETED queue",
+                        &render_task.project_uuid, frame
+                    );
+                    project.completed_frames.push_back(frame);
+                    let event = Event::frame_completed(project, frame, &self.working_dir);
+                    let _ = self.event_send.send(event);
+                }
+                Err(failure) => {
+                    warn!(
+                        "Project {} frame {} failed on {}: {}",
+                        &render_task.project_uuid, frame, &worker.name, &failure
+                    );
+                    for line in &failure.stderr_tail {
+                        debug!("  {}", line);
+                    }
+                    // Retry the frame if it has attempts left, otherwise move it to the failed
+                    // queue
+                    let error = failure.to_string();
+                    if project.record_failure(frame, Some(worker.name.clone()), failure) {
+                        debug!(
+                            "Retrying project {} frame {} (attempt {} of {})",
+                            &render_task.project_uuid,
+                            frame,
+                            project.attempts[&frame].len() + 1,
+                            project.retry_policy.max_attempts
+                        );
+                        retrying = true;
+                    } else {
+                        debug!(
+                            "Moving project {} frame {} to the FAILED queue",
+                            &render_task.project_uuid, frame
+                        );
+                        let event = Event::frame_failed(project, frame, error, &self.working_dir);
+                        let _ = self.event_send.send(event);
+                    }
+                }
+            }
+        }
+        // Move the frames that were not rendered back to the front of the waiting queue (in order)
+        for &frame in unrendered.iter().rev() {
+            debug!(
+                "Moving project {} frame {} back to the WAITING queue",
+                &render_task.project_uuid, frame
+            );
+            project.waiting_frames.push_front(frame);
+            retrying = true;
+        }
+        let mut encode = false;
+        if num_completed > 0 && project.complete() {
+            // Print a message if the project is complete
+            info!("Project \"{}\" is finished", project);
+            let _ = self.event_send.send(Event::project_completed(project, &self.working_dir));
+            encode = project.video.is_some();
+        } else if project.num_waiting() == 0
+            && project.num_delayed() == 0
+            && project.num_assigned() == 0
+            && project.num_failed() > 0
+        {
+            // If this was the last assigned frame, check if there are failed frames
             error!("Some frames of \"{}\" failed to render", project);
+            let _ = self.event_send.send(Event::frames_failed(project));
+        }
+        if retrying {
+            self.queue_project(render_task.project_uuid);
+        }
+        if encode {
+            self.start_encoding(render_task.project_uuid);
+        }
+    }
+
+    /// Encode the completed frames of a project into a video in a new thread
+    fn start_encoding(&mut self, project_uuid: Uuid) {
+        let project = self.projects.get_mut(&project_uuid).unwrap();
+        let settings = match &project.video {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+        info!("Encoding video of \"{}\" ({})", project, &settings);
+        project.video_state = Some(VideoState::Encoding);
+        self.state_changed = true;
+        let mut frames: Vec<Frame> = project.completed_frames.iter().copied().collect();
+        frames.sort_unstable();
+        let output_ext = project.output_ext;
+        let working_dir = self.working_dir.clone();
+        let result_send = self.result_send.clone();
+        thread::spawn(move || {
+            let result = encode_video(&working_dir, &project_uuid, &frames, output_ext, &settings)
+                .map_err(|error| error.to_string());
+            let _ = result_send.send(SchedulerResultMessage::VideoEncoded(project_uuid, result));
+        });
+    }
+
+    /// Handle the result of encoding the video of a project
+    fn handle_encoded(&mut self, project_uuid: Uuid, result: Result<(), String>) {
+        // Ignore the result if the project was removed while it was being encoded
+        let project = match self.projects.get_mut(&project_uuid) {
+            Some(project) => project,
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                info!("Video of \"{}\" is finished", project);
+                project.video_state = Some(VideoState::Finished);
+            }
+            Err(error) => {
+                error!("Error encoding video of \"{}\": {}", project, &error);
+                project.video_state = Some(VideoState::Failed(error));
+            }
+        }
+        self.state_changed = true;
+    }
+
+    /// Delete the files of a removed project
+    /// Project files are kept until no remaining project shares them
+    fn remove_project_files(&self, project: &Project) {
+        let is_shared =
+            |file_uuid| self.projects.values().any(|other| other.file_uuid() == file_uuid);
+        let project_dir = get_project_dir(&self.working_dir, &project.uuid);
+        let result = if is_shared(project.uuid) {
+            // Only delete the frames and logs
+            let project_file = get_project_file(&self.working_dir, &project.uuid);
+            fs::read_dir(&project_dir).and_then(|entries| {
+                for entry in entries {
+                    let path = entry?.path();
+                    if path != project_file {
+                        fs::remove_file(path)?;
+                    }
+                }
+                Ok(())
+            })
+        } else {
+            fs::remove_dir_all(project_dir)
+        };
+        if let Err(error) = result {
+            error!("Error removing files of project \"{}\": {}", project, error);
+        }
+        // Delete the shared project file if this was the last project using it
+        let file_uuid = project.file_uuid();
+        if file_uuid != project.uuid
+            && !self.projects.contains_key(&file_uuid)
+            && !is_shared(file_uuid)
+        {
+            debug!("Removing project file of project {}", file_uuid);
+            if let Err(error) = fs::remove_dir_all(get_project_dir(&self.working_dir, &file_uuid)) {
+                error!("Error removing files of project {}: {}", file_uuid, error);
+            }
         }
     }
 
     /// Handle a management message
     fn handle_manage_msg(&mut self, message: SchedulerManageMessage) {
+        // Worker messages and queries do not change the state of the projects
+        // (removing a project changes the state, but the project is removed from it right away)
+        if !matches!(
+            message,
+            SchedulerManageMessage::ListProjects(_)
+                | SchedulerManageMessage::FindProject(..)
+                | SchedulerManageMessage::ListWorkers(_)
+                | SchedulerManageMessage::ReleaseWorker(_)
+        ) {
+            self.state_changed = true;
+        }
         match message {
             // Add a project to the queue
             SchedulerManageMessage::AddProject(project) => {
                 info!("Adding project \"{}\"", &project);
-                self.queue.push_back(project.uuid.clone());
-                assert!(self.projects.insert(project.uuid.clone(), project).is_none());
+                let _ = self.event_send.send(Event::project_added(&project));
+                self.queue.push_back(project.uuid);
+                assert!(self.projects.insert(project.uuid, *project).is_none());
             }
             // Retry a project's failed frames
             SchedulerManageMessage::RetryFailed(project_uuid) => {
                 match self.projects.get_mut(&project_uuid) {
+                    Some(project) if project.cancelled => {
+                        error!("Project \"{}\" has been cancelled", project)
+                    }
                     Some(project) => {
                         // Move failed frames back to the waiting queue
                         project.retry_failed();
                         // Add the project to the queue if it is not already present
-                        if !self.queue.contains(&project_uuid) {
-                            self.queue.push_back(project_uuid);
-                        }
+                        self.queue_project(project_uuid);
+                    }
+                    None => error!("Project {} not found", project_uuid),
+                }
+            }
+            // Stop assigning a project's frames until it is resumed
+            SchedulerManageMessage::PauseProject(project_uuid) => {
+                match self.projects.get_mut(&project_uuid) {
+                    Some(project) => {
+                        info!("Pausing project \"{}\"", project);
+                        project.paused = true;
+                        // Remove the project from the queue
+                        self.queue.retain(|uuid| *uuid != project_uuid);
+                    }
+                    None => error!("Project {} not found", project_uuid),
+                }
+            }
+            // Start assigning a paused project's frames again
+            SchedulerManageMessage::ResumeProject(project_uuid) => {
+                match self.projects.get_mut(&project_uuid) {
+                    Some(project) if project.cancelled => {
+                        error!("Project \"{}\" has been cancelled", project)
+                    }
+                    Some(project) => {
+                        info!("Resuming project \"{}\"", project);
+                        project.paused = false;
+                        // Add the project to the queue if it is not already present
+                        self.queue_project(project_uuid);
                     }
                     None => error!("Project {} not found", project_uuid),
                 }
             }
+            // Stop assigning a project's frames and abort the frames being rendered
+            SchedulerManageMessage::CancelProject(project_uuid) => {
+                match self.projects.get_mut(&project_uuid) {
+                    Some(project) => {
+                        info!("Cancelling project \"{}\"", project);
+                        project.cancelled = true;
+                        // Remove the project from the queue
+                        self.queue.retain(|uuid| *uuid != project_uuid);
+                        self.policy.project_removed(&project_uuid);
+                        self.abort_project(&project_uuid);
+                    }
+                    None => error!("Project {} not found", project_uuid),
+                }
+            }
+            // Cancel a project and delete it along with its files
+            SchedulerManageMessage::RemoveProject(project_uuid) => {
+                match self.projects.remove(&project_uuid) {
+                    Some(project) => {
+                        info!("Removing project \"{}\"", project);
+                        // Remove the project from the queue
+                        self.queue.retain(|uuid| *uuid != project_uuid);
+                        self.policy.project_removed(&project_uuid);
+                        self.abort_project(&project_uuid);
+                        self.remove_project_files(&project);
+                    }
+                    None => error!("Project {} not found", project_uuid),
+                }
+            }
+            // Get the status of all of the projects
+            SchedulerManageMessage::ListProjects(status_send) => {
+                let mut projects: Vec<&Project> = self.projects.values().collect();
+                projects.sort_by_key(|project| project.submitted);
+                let num_workers = self.num_available_workers();
+                let status =
+                    projects.into_iter().map(|project| project.status(num_workers)).collect();
+                // Ignore the error if the receiver has gone away
+                let _ = status_send.send(status);
+            }
+            // Get the status of the project a reference refers to (without getting the status of
+            // every other project)
+            SchedulerManageMessage::FindProject(reference, status_send) => {
+                let mut projects =
+                    self.projects.values().filter(|project| project.matches(&reference));
+                let status = match (projects.next(), projects.next()) {
+                    (None, _) => Err(format!("project \"{}\" not found", reference)),
+                    (Some(project), None) => Ok(project.status(self.num_available_workers())),
+                    (Some(_), Some(_)) => Err(format!("project \"{}\" is ambiguous", reference)),
+                };
+                // Ignore the error if the receiver has gone away
+                let _ = status_send.send(status);
+            }
+            // Get the status of all of the workers
+            SchedulerManageMessage::ListWorkers(status_send) => {
+                let status = self.worker_status();
+                // Ignore the error if the receiver has gone away
+                let _ = status_send.send(status);
+            }
+            // Let a quarantined worker take tasks again
+            SchedulerManageMessage::ReleaseWorker(worker) => match self.workers.get_mut(&worker) {
+                Some(stats) => {
+                    info!("Releasing worker {} from quarantine", &worker);
+                    stats.quarantined = false;
+                    stats.consecutive_failures = 0;
+                    self.set_quarantined(&worker, false);
+                }
+                None => error!("Worker {} not found", worker),
+            },
         }
     }
+
+    /// Get the number of connected workers that are not quarantined
+    fn num_available_workers(&self) -> usize {
+        self.connections
+            .values()
+            .filter(|connection| !self.workers[&connection.name].quarantined)
+            .count()
+    }
+
+    /// Get the status of every worker that has connected
+    fn worker_status(&self) -> Vec<WorkerStatus> {
+        self.workers
+            .iter()
+            .map(|(name, stats)| WorkerStatus {
+                name: name.clone(),
+                connections: self
+                    .connections
+                    .values()
+                    .filter(|connection| &connection.name == name)
+                    .count(),
+                num_completed: stats.num_completed,
+                num_failed: stats.num_failed,
+                consecutive_failures: stats.consecutive_failures,
+                quarantined: stats.quarantined,
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,90 @@
+use crate::common::file::{get_project_dir, get_state_file};
+use crate::server::project::Project;
+use log::{debug, info};
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Load the saved projects from the working directory, if there are any
+pub(super) fn load_projects(working_dir: &Path) -> io::Result<Vec<Project>> {
+    let state_file = get_state_file(working_dir);
+    if !state_file.is_file() {
+        return Ok(Vec::new());
+    }
+    debug!("Loading state from {:?}...", &state_file);
+    let mut projects: Vec<Project> =
+        serde_json::from_reader(BufReader::new(File::open(&state_file)?))?;
+    // Frames that were being rendered when the server stopped have to be rendered again
+    for project in &mut projects {
+        project.recover(&get_project_dir(working_dir, &project.uuid));
+    }
+    info!("Loaded {} project(s)", projects.len());
+    Ok(projects)
+}
+
+/// Save the projects to the working directory, replacing the previous state
+pub(super) fn save_projects<'a>(
+    working_dir: &Path,
+    projects: impl Iterator<Item = &'a Project>,
+) -> io::Result<()> {
+    let state_file = get_state_file(working_dir);
+    // Write to a temporary file first so that the state is never left half-written
+    let temp_file = state_file.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_file)?);
+    serde_json::to_writer(&mut writer, &projects.collect::<Vec<_>>())?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(temp_file, state_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::file::get_frame_file;
+    use crate::common::retry::FrameAttempt;
+    use crate::server::project::tests::settings;
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    #[test]
+    fn round_trip() {
+        let working_dir = std::env::temp_dir().join(format!("tinyrf-state-{}", Uuid::new_v4()));
+        let mut project = Project::new(settings("1-10x2"));
+        fs::create_dir_all(get_project_dir(&working_dir, &project.uuid)).unwrap();
+        // Frame 1 was rendered, frame 3 is being rendered and frame 5 failed
+        project.waiting_frames = (7..=9).step_by(2).collect();
+        project.completed_frames.push_back(1);
+        project.assigned_frames.insert(3, Some(String::from("worker")));
+        project.failed_frames.push_back(5);
+        let attempt = FrameAttempt { worker: None, time: SystemTime::now(), failure: None };
+        project.attempts.insert(5, vec![attempt]);
+        project.paused = true;
+        File::create(get_frame_file(&working_dir, &project.uuid, 1, project.output_ext)).unwrap();
+
+        save_projects(&working_dir, std::iter::once(&project)).unwrap();
+        let loaded = load_projects(&working_dir);
+        fs::remove_dir_all(&working_dir).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!((loaded.uuid, &loaded.name), (project.uuid, &project.name));
+        assert_eq!(loaded.frame_spec, project.frame_spec);
+        assert!(loaded.paused);
+        // The frame that was being rendered is rendered again first, and no frame is lost or
+        // duplicated
+        assert!(loaded.assigned_frames.is_empty());
+        assert_eq!(loaded.waiting_frames, vec![3, 7, 9]);
+        assert_eq!(loaded.completed_frames, vec![1]);
+        assert_eq!(loaded.failed_frames, vec![5]);
+        assert_eq!(loaded.attempts[&5].len(), 1);
+        assert_eq!(loaded.num_frames(), 5);
+    }
+
+    #[test]
+    fn no_saved_state() {
+        let working_dir = std::env::temp_dir().join(format!("tinyrf-state-{}", Uuid::new_v4()));
+        assert!(load_projects(&working_dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::common::file::{get_frame_file_name, get_project_dir, get_video_file_name};
+use crate::common::render_task::{FileExt, Frame};
+use crate::common::video::{VideoCodec, VideoSettings};
+use failure::Fail;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use uuid::Uuid;
+
+/// The name of the list of frames passed to ffmpeg
+const FRAME_LIST_FILE: &str = "frames.txt";
+
+pub(super) type VideoResult<T> = Result<T, VideoError>;
+
+#[derive(Fail, Debug)]
+pub(super) enum VideoError {
+    #[fail(display = "I/O error: {}", 0)]
+    IoError(#[fail(cause)] io::Error),
+    #[fail(display = "error starting ffmpeg: {}", 0)]
+    ExecFailed(#[fail(cause)] io::Error),
+    #[fail(display = "ffmpeg exited with error: {}: {}", 0, 1)]
+    ExitStatus(ExitStatus, String),
+}
+
+/// Encode the frames of a project into a video with ffmpeg (blocking until it is done)
+pub(super) fn encode_video(
+    working_dir: &Path,
+    project_uuid: &Uuid,
+    frames: &[Frame],
+    output_ext: FileExt,
+    settings: &VideoSettings,
+) -> VideoResult<()> {
+    let project_dir = get_project_dir(working_dir, project_uuid);
+
+    // List the frames for ffmpeg's concat demuxer, which (unlike an image sequence pattern)
+    // handles gaps in the frame numbers
+    // The last frame is listed twice, since the duration of the last entry is ignored
+    let duration = 1.0 / settings.frame_rate;
+    let mut frame_list = File::create(project_dir.join(FRAME_LIST_FILE))?;
+    writeln!(frame_list, "ffconcat version 1.0")?;
+    for frame in frames {
+        writeln!(frame_list, "file '{}'", get_frame_file_name(*frame, output_ext))?;
+        writeln!(frame_list, "duration {}", duration)?;
+    }
+    if let Some(frame) = frames.last() {
+        writeln!(frame_list, "file '{}'", get_frame_file_name(*frame, output_ext))?;
+    }
+    drop(frame_list);
+
+    // Encode to a partial file first, so that an interrupted encode is never downloaded
+    let video_file_name = get_video_file_name(settings.codec);
+    let partial_file_name = format!("{}.part", video_file_name);
+    let mut command = Command::new("ffmpeg");
+    command
+        .current_dir(&project_dir)
+        .args(["-y", "-nostdin", "-loglevel", "error"])
+        .args(["-f", "concat", "-i", FRAME_LIST_FILE])
+        .arg("-r")
+        .arg(settings.frame_rate.to_string());
+    match settings.codec {
+        VideoCodec::H264 => command
+            .args(["-c:v", "libx264", "-crf", "18", "-pix_fmt", "yuv420p"])
+            // H.264 with 4:2:0 chroma subsampling needs an even width and height
+            .args(["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2"])
+            .args(["-movflags", "+faststart", "-f", "mp4"]),
+        VideoCodec::ProRes => command
+            .args(["-c:v", "prores_ks", "-profile:v", "3", "-pix_fmt", "yuv422p10le"])
+            .args(["-f", "mov"]),
+        VideoCodec::Gif => command
+            // Generate a palette from the frames rather than using a generic one
+            .args(["-filter_complex", "split[a][b];[a]palettegen[p];[b][p]paletteuse"])
+            .args(["-f", "gif"]),
+    };
+    command.arg(&partial_file_name).stdout(Stdio::null()).stderr(Stdio::piped());
+
+    let output = command.output().map_err(VideoError::ExecFailed)?;
+    let _ = fs::remove_file(project_dir.join(FRAME_LIST_FILE));
+    if !output.status.success() {
+        let _ = fs::remove_file(project_dir.join(&partial_file_name));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().last().unwrap_or_default().to_string();
+        return Err(VideoError::ExitStatus(output.status, message));
+    }
+    fs::rename(project_dir.join(partial_file_name), project_dir.join(video_file_name))?;
+    Ok(())
+}
+
+impl From<io::Error> for VideoError {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
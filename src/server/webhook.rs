@@ -0,0 +1,389 @@
+use crate::server::events::EventKind;
+use crossbeam_channel::Sender;
+use failure::Fail;
+use log::{debug, error, warn};
+use serde::Serialize;
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long to wait for a webhook server to connect or respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait before the first retry (doubling after each attempt)
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest time to wait between attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type WebhookResult<T> = Result<T, WebhookError>;
+
+#[derive(Fail, Debug)]
+enum WebhookError {
+    #[fail(display = "I/O error: {}", 0)]
+    IoError(#[fail(cause)] io::Error),
+    #[fail(display = "invalid response: {:?}", 0)]
+    InvalidResponse(String),
+    #[fail(display = "server responded with status {}", 0)]
+    HttpStatus(u16),
+}
+
+/// An HTTP URL events are sent to
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+/// A record of an attempt at sending an event to a webhook
+#[derive(Serialize)]
+struct Delivery<'a> {
+    /// When the attempt finished, in seconds since the Unix epoch
+    timestamp: u64,
+    url: String,
+    event: EventKind,
+    attempt: u32,
+    delivered: bool,
+    /// The HTTP status the server responded with, if it responded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Start sending events to a webhook URL in a new thread
+/// Events are sent in order, retrying each one until it is delivered or runs out of attempts
+pub(super) fn start(
+    url: WebhookUrl,
+    max_attempts: u32,
+    delivery_log: Arc<Mutex<File>>,
+) -> Sender<(EventKind, Arc<String>)> {
+    let (payload_send, payload_recv) = crossbeam_channel::unbounded::<(EventKind, Arc<String>)>();
+    thread::spawn(move || {
+        for (kind, payload) in payload_recv {
+            deliver(&url, kind, &payload, max_attempts, &delivery_log);
+        }
+    });
+    payload_send
+}
+
+/// Send an event to a webhook URL, retrying until it is delivered or runs out of attempts
+/// Returns whether the event was delivered
+fn deliver(
+    url: &WebhookUrl,
+    kind: EventKind,
+    payload: &str,
+    max_attempts: u32,
+    delivery_log: &Mutex<File>,
+) -> bool {
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=max_attempts {
+        let result = url.post(payload);
+        let (status, error) = match &result {
+            Ok(status) => (Some(*status), None),
+            Err(WebhookError::HttpStatus(status)) => (Some(*status), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+        log_delivery(delivery_log, url, kind, attempt, result.is_ok(), status, error);
+        match result {
+            Ok(_) => {
+                debug!("Sent {} event to {}", kind, url);
+                return true;
+            }
+            // Client errors other than timeouts and rate limits will not go away
+            Err(WebhookError::HttpStatus(status))
+                if (400..500).contains(&status) && status != 408 && status != 429 =>
+            {
+                error!("Webhook {} rejected {} event with status {}", url, kind, status);
+                return false;
+            }
+            Err(error) if attempt == max_attempts => {
+                error!("Error sending {} event to {}, giving up: {}", kind, url, error);
+            }
+            Err(error) => {
+                warn!(
+                    "Error sending {} event to {} (attempt {} of {}): {}",
+                    kind, url, attempt, max_attempts, error
+                );
+                thread::sleep(backoff);
+                backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+            }
+        }
+    }
+    false
+}
+
+/// Record an attempt at sending an event in the delivery log
+fn log_delivery(
+    delivery_log: &Mutex<File>,
+    url: &WebhookUrl,
+    event: EventKind,
+    attempt: u32,
+    delivered: bool,
+    status: Option<u16>,
+    error: Option<String>,
+) {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let delivery = Delivery {
+        timestamp,
+        url: url.to_string(),
+        event,
+        attempt,
+        delivered,
+        status,
+        error: error.as_deref(),
+    };
+    let mut delivery_log = delivery_log.lock().unwrap();
+    if let Err(error) = serde_json::to_writer(&mut *delivery_log, &delivery)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(delivery_log))
+    {
+        error!("Error writing to webhook delivery log: {}", error);
+    }
+}
+
+impl WebhookUrl {
+    /// Send a JSON payload in a POST request, returning the status of a successful response
+    fn post(&self, payload: &str) -> WebhookResult<u16> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        // The connection is closed after the response, so only the status line has to be read
+        write!(
+            &stream,
+            "POST {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             User-Agent: tinyrf/{}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\
+             \r\n\
+             {}",
+            &self.path,
+            self.authority(),
+            env!("CARGO_PKG_VERSION"),
+            payload.len(),
+            payload
+        )?;
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line)?;
+        // The status line looks like "HTTP/1.1 200 OK"
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .filter(|_| status_line.starts_with("HTTP/"))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| WebhookError::InvalidResponse(status_line.trim_end().to_string()))?;
+        if (200..300).contains(&status) {
+            Ok(status)
+        } else {
+            Err(WebhookError::HttpStatus(status))
+        }
+    }
+
+    /// Get the host and port, as used in the Host header
+    fn authority(&self) -> String {
+        // IPv6 addresses are enclosed in brackets
+        let host =
+            if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == 80 {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+}
+
+impl fmt::Display for WebhookUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+impl FromStr for WebhookUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("https://") {
+            return Err(String::from("HTTPS is not supported, use an http:// URL"));
+        }
+        let rest = s.strip_prefix("http://").ok_or_else(|| format!("invalid URL: {}", s))?;
+        // Split the URL into the authority and the path
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        // Split the authority into the host and the port, which may follow an IPv6 address
+        let (host, port) = match authority.rfind(':') {
+            Some(index) if !authority[index..].contains(']') => {
+                let port = &authority[index + 1..];
+                let port = port.parse().map_err(|_| format!("invalid port: {}", port))?;
+                (&authority[..index], port)
+            }
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("invalid URL: {}", s));
+        }
+        Ok(WebhookUrl { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+impl From<io::Error> for WebhookError {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::thread::JoinHandle;
+    use uuid::Uuid;
+
+    /// Start a local HTTP server that responds to a request with each of the statuses in turn,
+    /// returning its URL and the bodies of the requests it received
+    fn serve(statuses: &[u16]) -> (WebhookUrl, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/hook", listener.local_addr().unwrap().port());
+        let statuses = statuses.to_vec();
+        let server = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!(line, "POST /hook HTTP/1.1\r\n");
+                let mut length = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim_end().parse().unwrap();
+                    }
+                }
+                let mut body = String::new();
+                reader.take(length).read_to_string(&mut body).unwrap();
+                bodies.push(body);
+                write!(&stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            }
+            bodies
+        });
+        (url.parse().unwrap(), server)
+    }
+
+    /// Create an empty delivery log in the temporary directory
+    fn delivery_log() -> (PathBuf, Mutex<File>) {
+        let path = std::env::temp_dir().join(format!("tinyrf-webhooks-{}.log", Uuid::new_v4()));
+        let file = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        (path, Mutex::new(file))
+    }
+
+    /// Read and remove a delivery log
+    fn read_deliveries(path: &Path) -> Vec<serde_json::Value> {
+        let deliveries = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        deliveries.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_url() {
+        let url: WebhookUrl = "http://example.com".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("example.com", 80, "/"));
+        let url: WebhookUrl = "http://example.com:8080/events?a=b".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("example.com", 8080));
+        assert_eq!(url.path, "/events?a=b");
+        assert_eq!(url.to_string(), "http://example.com:8080/events?a=b");
+    }
+
+    #[test]
+    fn parse_ipv6_url() {
+        let url: WebhookUrl = "http://[::1]/hook".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 80));
+        let url: WebhookUrl = "http://[::1]:9000/hook".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 9000));
+        assert_eq!(url.to_string(), "http://[::1]:9000/hook");
+    }
+
+    #[test]
+    fn reject_invalid_urls() {
+        assert!("https://example.com/hook".parse::<WebhookUrl>().unwrap_err().contains("HTTPS"));
+        assert!("example.com/hook".parse::<WebhookUrl>().is_err());
+        assert!("http://:8080/hook".parse::<WebhookUrl>().is_err());
+        assert!("http://example.com:port/hook".parse::<WebhookUrl>().is_err());
+    }
+
+    #[test]
+    fn deliver_success() {
+        let (url, server) = serve(&[204]);
+        let (path, log) = delivery_log();
+        assert!(deliver(&url, EventKind::ProjectAdded, "{\"a\":1}", 3, &log));
+        assert_eq!(server.join().unwrap(), vec!["{\"a\":1}"]);
+        let deliveries = read_deliveries(&path);
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0]["url"], url.to_string());
+        assert_eq!(deliveries[0]["event"], "project_added");
+        assert_eq!(deliveries[0]["attempt"], 1);
+        assert_eq!(deliveries[0]["delivered"], true);
+        assert_eq!(deliveries[0]["status"], 204);
+        assert!(deliveries[0].get("error").is_none());
+    }
+
+    #[test]
+    fn client_error_is_not_retried() {
+        let (url, server) = serve(&[404]);
+        let (path, log) = delivery_log();
+        assert!(!deliver(&url, EventKind::ProjectCompleted, "{}", 3, &log));
+        assert_eq!(server.join().unwrap().len(), 1);
+        let deliveries = read_deliveries(&path);
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0]["delivered"], false);
+        assert_eq!(deliveries[0]["status"], 404);
+    }
+
+    #[test]
+    fn server_error_is_retried() {
+        let (url, server) = serve(&[503, 200]);
+        let (path, log) = delivery_log();
+        assert!(deliver(&url, EventKind::WorkerConnected, "{}", 3, &log));
+        assert_eq!(server.join().unwrap().len(), 2);
+        let deliveries = read_deliveries(&path);
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!((&deliveries[0]["attempt"], &deliveries[0]["status"]), (&1.into(), &503.into()));
+        assert_eq!(deliveries[0]["delivered"], false);
+        assert_eq!((&deliveries[1]["attempt"], &deliveries[1]["status"]), (&2.into(), &200.into()));
+        assert_eq!(deliveries[1]["delivered"], true);
+    }
+
+    #[test]
+    fn connection_error_is_logged() {
+        // Bind and drop a listener to get a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url: WebhookUrl = format!("http://127.0.0.1:{}/hook", port).parse().unwrap();
+        let (path, log) = delivery_log();
+        assert!(!deliver(&url, EventKind::WorkerDisconnected, "{}", 1, &log));
+        let deliveries = read_deliveries(&path);
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0]["delivered"], false);
+        assert!(deliveries[0].get("status").is_none());
+        assert!(deliveries[0]["error"].as_str().unwrap().starts_with("I/O error"));
+    }
+}
@@ -1,19 +1,35 @@
 pub(super) mod args;
+mod backoff;
+mod progress;
 mod render;
+mod script;
 
-use crate::common::file::{get_project_dir, get_project_file, init_working_dir};
-use crate::common::message::{ServerMessage, WorkerMessage};
-use crate::common::net::{read_json, write_json};
+use crate::common::file::{
+    get_frame_file, get_log_file, get_project_dir, get_project_file, init_working_dir,
+};
+use crate::common::message::{ServerMessage, WorkerMessage, HEARTBEAT_INTERVAL};
+use crate::common::net::{read_json_resumable, write_json};
+use crate::common::render_task::{Frame, RenderProgress, RenderTask};
 use crate::common::transfer::{recv_file, send_file};
 use crate::worker::args::WorkerArgs;
+use crate::worker::backoff::Backoff;
+use crate::worker::render::FrameOutput;
 use failure::Fail;
 use log::{debug, error, info};
+use std::collections::BTreeMap;
 use std::io::{BufReader, BufWriter};
 use std::net::TcpStream;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
 use uuid::Uuid;
 
+/// How often to check whether the render process has exited (and for messages from the server)
+const RENDER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The minimum time between progress updates sent to the server
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
 pub(super) type WorkerResult<T> = Result<T, WorkerError>;
 
 #[derive(Fail, Debug)]
@@ -26,6 +42,16 @@ pub(super) enum WorkerError {
     IoError(#[fail(cause)] io::Error),
     #[fail(display = "Error transferring file: {}", 0)]
     TransferFailed(#[fail(cause)] io::Error),
+    #[fail(display = "Unexpected message: {:?}", 0)]
+    UnexpectedMessage(Box<ServerMessage>),
+}
+
+/// The outcome of a render
+enum RenderOutcome {
+    /// The render process exited, with the output of each frame
+    Finished(BTreeMap<Frame, FrameOutput>),
+    /// The server told the worker to stop the render
+    Aborted,
 }
 
 pub(super) struct Worker<'a> {
@@ -33,39 +59,67 @@ pub(super) struct Worker<'a> {
     reader: BufReader<&'a TcpStream>,
     writer: BufWriter<&'a TcpStream>,
     working_dir: PathBuf,
+    /// A partially-read message from the server
+    line: Vec<u8>,
 }
 
 impl<'a> Worker<'a> {
-    /// Connect to the server and handle messages
+    /// Connect to the server and handle messages, reconnecting whenever the connection is lost
     pub(super) fn run(args: WorkerArgs) -> WorkerResult<()> {
         // If no name has been specified, try to use the hostname
         let name = args.name.or_else(|| hostname::get().ok().and_then(|s| s.into_string().ok()));
 
-        // Initialize the working directory
+        // Initialize the working directory (kept across connections so project files are reused)
         let working_dir = init_working_dir("worker").map_err(WorkerError::WorkingDirInitFailed)?;
 
-        info!("Connecting to {}:{}...", args.address, args.port);
+        let mut backoff = Backoff::new();
+        loop {
+            info!("Connecting to {}:{}...", args.address, args.port);
 
-        // Attempt to open a connection to the server
-        let stream = TcpStream::connect((args.address.as_str(), args.port))
-            .map_err(WorkerError::ConnectFailed)?;
+            // Attempt to open a connection to the server
+            match TcpStream::connect((args.address.as_str(), args.port)) {
+                Ok(stream) => {
+                    info!("Connected to server!");
+                    backoff.reset();
+                    let error = Self::handle_connection(&stream, name.clone(), &working_dir);
+                    error!("Connection lost: {}", error);
+                }
+                Err(error) => error!("{}", WorkerError::ConnectFailed(error)),
+            }
 
-        info!("Connected to server!");
+            // Wait before trying to reconnect
+            let delay = backoff.next_delay();
+            info!("Reconnecting in {:.1}s...", delay.as_secs_f32());
+            thread::sleep(delay);
+        }
+    }
 
+    /// Handle messages from the server until an error occurs
+    fn handle_connection(
+        stream: &TcpStream,
+        name: Option<String>,
+        working_dir: &Path,
+    ) -> WorkerError {
         let mut worker = Worker {
             name,
-            reader: BufReader::new(&stream),
-            writer: BufWriter::new(&stream),
-            working_dir,
+            reader: BufReader::new(stream),
+            writer: BufWriter::new(stream),
+            working_dir: working_dir.to_path_buf(),
+            line: Vec::new(),
         };
 
         // Send the init message
-        worker.write_message(WorkerMessage::Init { name: worker.name.clone() })?;
+        if let Err(error) = worker.write_message(WorkerMessage::Init { name: worker.name.clone() })
+        {
+            return error.into();
+        }
 
         // Read and handle messages from the server
         loop {
-            let message = worker.read_message()?;
-            worker.handle_message(message)?;
+            let result = worker.read_message().map_err(WorkerError::from);
+            if let Err(error) = result.and_then(|message| worker.handle_message(message)) {
+                return error;
+            }
         }
     }
 
@@ -75,29 +129,135 @@ impl<'a> Worker<'a> {
             ServerMessage::Idle => {
                 info!("Idle");
                 Ok(())
-            },
+            }
             ServerMessage::StartRender(task) => {
-                // Download the project file
+                // Download the project file (unless it was downloaded for an earlier task)
                 info!("Downloading project \"{}\"...", task.project_name);
-                self.download_project(&task.project_uuid)?;
-                // Render the frame
-                info!("Rendering frame {}...", task.frame);
-                match render::render(&task, &self.working_dir) {
-                    Ok(output_file) => {
-                        info!("Uploading file {:?}...", output_file.file_name().unwrap());
-                        // Send the result to the server
-                        self.write_message(WorkerMessage::RenderResult(Ok(())))?;
-                        // Upload the output file
-                        self.upload_output(&output_file)?;
+                self.download_project(&task.file_uuid)?;
+                // Create the directory for the output of the task
+                let project_dir = get_project_dir(&self.working_dir, &task.project_uuid);
+                if !project_dir.is_dir() {
+                    fs::create_dir(project_dir)?;
+                }
+                // Render the frames
+                if task.start_frame == task.end_frame {
+                    info!("Rendering frame {}...", task.start_frame);
+                } else {
+                    info!("Rendering frames {}-{}...", task.start_frame, task.end_frame);
+                }
+                match self.render(&task)? {
+                    RenderOutcome::Finished(outputs) => {
+                        for (frame, output) in &outputs {
+                            if let Err(failure) = output {
+                                error!("Frame {} failed: {}", frame, failure);
+                            }
+                        }
+                        // Send the result of each frame to the server
+                        let results = outputs
+                            .iter()
+                            .map(|(&frame, output)| {
+                                (frame, output.as_ref().map(|_| ()).map_err(Clone::clone))
+                            })
+                            .collect();
+                        self.write_message(WorkerMessage::RenderResult(results))?;
+                        self.wait_upload_ready()?;
+                        // Upload the output files of the frames that were rendered
+                        for output_file in
+                            outputs.values().filter_map(|output| output.as_ref().ok())
+                        {
+                            info!("Uploading file {:?}...", output_file.file_name().unwrap());
+                            self.upload_output(output_file)?;
+                        }
                         info!("Upload complete");
-                        Ok(())
                     }
-                    Err(error) => {
-                        error!("Render failed: {}", error);
-                        // Send the result to the server
-                        Ok(self.write_message(WorkerMessage::RenderResult(Err(())))?)
+                    RenderOutcome::Aborted => {
+                        info!("Render aborted");
+                        // Remove the frames that were rendered before the abort
+                        for frame in task.frames() {
+                            let output_file = get_frame_file(
+                                &self.working_dir,
+                                &task.project_uuid,
+                                frame,
+                                task.output_ext,
+                            );
+                            let _ = fs::remove_file(output_file);
+                        }
+                        // Let the server know that the render stopped (there is no log to upload)
+                        let _ = fs::remove_file(get_log_file(&self.working_dir, &task));
+                        return Ok(self.write_message(WorkerMessage::RenderAborted)?);
                     }
                 }
+                // Upload the render log
+                self.upload_log(&task)
+            }
+            // The render already finished before the abort message arrived
+            ServerMessage::AbortRender => {
+                debug!("No render to abort");
+                Ok(())
+            }
+            message => Err(WorkerError::UnexpectedMessage(Box::new(message))),
+        }
+    }
+
+    /// Wait for the server to be ready for the uploads after sending the result of a render
+    /// The server may have told the worker to abort the render before the result arrived
+    fn wait_upload_ready(&mut self) -> WorkerResult<()> {
+        loop {
+            match self.read_message()? {
+                ServerMessage::UploadReady => return Ok(()),
+                ServerMessage::AbortRender => debug!("Render already finished, not aborting"),
+                message => return Err(WorkerError::UnexpectedMessage(Box::new(message))),
+            }
+        }
+    }
+
+    /// Render a task, checking for messages from the server while the render process runs
+    fn render(&mut self, task: &RenderTask) -> WorkerResult<RenderOutcome> {
+        self.reader.get_ref().set_read_timeout(Some(RENDER_POLL_INTERVAL))?;
+        let outcome = self.run_render(task);
+        self.reader.get_ref().set_read_timeout(None)?;
+        outcome
+    }
+
+    /// Render a task, sending progress updates (or heartbeats if there is no progress) to the
+    /// server until the render process exits or the server aborts the render
+    fn run_render(&mut self, task: &RenderTask) -> WorkerResult<RenderOutcome> {
+        let mut process = match render::start(task, &self.working_dir) {
+            Ok(process) => process,
+            Err(error) => {
+                // None of the frames can be rendered
+                let failure = error.into_failure(Vec::new());
+                let outputs = task.frames().map(|frame| (frame, Err(failure.clone()))).collect();
+                return Ok(RenderOutcome::Finished(outputs));
+            }
+        };
+        let mut last_message = Instant::now();
+        let mut last_progress = RenderProgress::default();
+        loop {
+            // Check whether the render has finished
+            if let Some(outputs) = process.try_finish() {
+                return Ok(RenderOutcome::Finished(outputs));
+            }
+            // Let the server know how far along the render is
+            let progress = process.progress();
+            if progress != last_progress && last_message.elapsed() >= PROGRESS_INTERVAL {
+                self.write_message(WorkerMessage::Progress(progress.clone()))?;
+                last_progress = progress;
+                last_message = Instant::now();
+            }
+            // Let the server know that the worker is still alive
+            if last_message.elapsed() >= HEARTBEAT_INTERVAL {
+                self.write_message(WorkerMessage::Heartbeat)?;
+                last_message = Instant::now();
+            }
+            // Wait for a message from the server until it is time to check the process again
+            match self.poll_message()? {
+                Some(ServerMessage::AbortRender) => {
+                    process.abort();
+                    return Ok(RenderOutcome::Aborted);
+                }
+                Some(message) => return Err(WorkerError::UnexpectedMessage(Box::new(message))),
+                None => (),
             }
         }
     }
@@ -124,13 +284,36 @@ impl<'a> Worker<'a> {
         Ok(fs::remove_file(output_file)?)
     }
 
+    /// Upload the render log of a task
+    fn upload_log(&mut self, task: &RenderTask) -> WorkerResult<()> {
+        let log_file = get_log_file(&self.working_dir, task);
+        // Send an empty log if the render process could not be started
+        if !log_file.is_file() {
+            fs::File::create(&log_file)?;
+        }
+        self.upload_output(&log_file)
+    }
+
     /// Read a message from the server (blocking)
     fn read_message(&mut self) -> io::Result<ServerMessage> {
-        let message = read_json(&mut self.reader)?;
+        let message = read_json_resumable(&mut self.reader, &mut self.line)?;
         debug!("Server -> {:?}", &message);
         Ok(message)
     }
 
+    /// Read a message from the server, returning `None` if the read timed out
+    fn poll_message(&mut self) -> io::Result<Option<ServerMessage>> {
+        match self.read_message() {
+            Ok(message) => Ok(Some(message)),
+            Err(error)
+                if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
     /// Send a message to the server
     fn write_message(&mut self, message: WorkerMessage) -> io::Result<()> {
         debug!("Server <- {:?}", &message);
@@ -0,0 +1,68 @@
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The delay before the first reconnection attempt
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+/// The maximum delay between reconnection attempts
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Exponential backoff with jitter for reconnecting to the server
+pub(super) struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    pub(super) fn new() -> Backoff {
+        Backoff { delay: INITIAL_DELAY }
+    }
+
+    /// Get the delay before the next attempt and double the delay for the attempt after that
+    pub(super) fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+        // Randomize the delay between 50% and 100% so that workers do not reconnect in lockstep
+        delay.mul_f64(0.5 + 0.5 * random_fraction())
+    }
+
+    /// Reset the delay after a successful connection
+    pub(super) fn reset(&mut self) {
+        self.delay = INITIAL_DELAY;
+    }
+}
+
+/// The number of random bits taken from a v4 UUID
+/// The low 62 bits are random (the bits above them hold the variant), and 53 of them fit exactly
+/// in the mantissa of a `f64`
+const RANDOM_BITS: u32 = 53;
+
+/// Get a random number in the range [0, 1)
+fn random_fraction() -> f64 {
+    // The random bits of a v4 UUID are a convenient source of randomness
+    let random = Uuid::new_v4().as_u128() as u64 & ((1 << RANDOM_BITS) - 1);
+    random as f64 / (1u64 << RANDOM_BITS) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_fraction_covers_range() {
+        let fractions: Vec<f64> = (0..1000).map(|_| random_fraction()).collect();
+        assert!(fractions.iter().all(|fraction| (0.0..1.0).contains(fraction)));
+        assert!(fractions.iter().any(|&fraction| fraction < 0.25));
+        assert!(fractions.iter().any(|&fraction| fraction >= 0.75));
+    }
+
+    #[test]
+    fn delay_doubles_with_jitter() {
+        let mut backoff = Backoff::new();
+        for attempt in 0..10 {
+            let max_delay = (INITIAL_DELAY * 2u32.pow(attempt)).min(MAX_DELAY);
+            let delay = backoff.next_delay();
+            assert!(delay >= max_delay / 2 && delay <= max_delay, "{:?}", delay);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= INITIAL_DELAY);
+    }
+}
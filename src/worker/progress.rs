@@ -0,0 +1,169 @@
+use crate::common::render_task::RenderProgress;
+use std::time::Duration;
+
+/// Update the progress of a render from a line of Blender's output
+/// Returns whether the line was a progress line
+///
+/// Progress lines look like this (the fields vary between versions and render engines):
+/// `Fra:1 Mem:12.00M (Peak 20.00M) | Time:00:01.23 | Remaining:00:10.50 | ... | Sample 3/128`
+pub(super) fn parse_progress_line(line: &str, progress: &mut RenderProgress) -> bool {
+    let frame = match line.strip_prefix("Fra:") {
+        Some(frame) => frame.split_whitespace().next().and_then(|frame| frame.parse().ok()),
+        None => return false,
+    };
+    // Start over when the next frame of an animation starts
+    if frame != progress.frame {
+        *progress = RenderProgress { frame, ..RenderProgress::default() };
+    }
+    for field in line.split('|').map(str::trim) {
+        if let Some(remaining) = field.strip_prefix("Remaining:") {
+            progress.remaining = parse_time(remaining);
+        } else if let Some(fraction) = parse_fraction(field) {
+            progress.fraction = Some(fraction);
+        }
+        if let Some(peak_memory) = parse_peak_memory(field) {
+            progress.peak_memory = Some(peak_memory);
+        }
+    }
+    true
+}
+
+/// Parse the fraction of a frame that has been rendered from a progress field
+fn parse_fraction(field: &str) -> Option<f32> {
+    // Cycles: "Sample 3/128", or "Path Tracing Tile 3/16, Sample 4/128" in older versions
+    if let Some(tile) = field.strip_prefix("Path Tracing Tile ") {
+        let (tile, sample) = match tile.find(", Sample ") {
+            Some(index) => (&tile[..index], parse_ratio(&tile[index + 9..]).unwrap_or(0.0)),
+            None => (tile, 0.0),
+        };
+        let (current, total) = parse_pair(tile)?;
+        return Some(((current - 1.0).max(0.0) + sample) / total);
+    }
+    if let Some(sample) = field.strip_prefix("Sample ") {
+        return parse_ratio(sample);
+    }
+    // Older versions: "Rendered 3/16 Tiles"
+    if let Some(tiles) = field.strip_prefix("Rendered ") {
+        return parse_ratio(tiles.trim_end_matches(" Tiles"));
+    }
+    // Eevee: "Rendering 5 / 64 samples"
+    if let Some(samples) = field.strip_prefix("Rendering ") {
+        return parse_ratio(samples.trim_end_matches(" samples"));
+    }
+    None
+}
+
+/// Parse the peak memory usage from a progress field ("Mem:12.00M (Peak 20.00M)" or
+/// "Mem:10M, Peak:20M")
+fn parse_peak_memory(field: &str) -> Option<String> {
+    if let Some(index) = field.find("(Peak ") {
+        let peak = &field[index + 6..];
+        return Some(peak[..peak.find(')')?].to_string());
+    }
+    let index = field.find("Peak:")?;
+    Some(field[index + 5..].trim().to_string())
+}
+
+/// Parse a ratio such as "3/16" or "5 / 64"
+fn parse_ratio(ratio: &str) -> Option<f32> {
+    let (current, total) = parse_pair(ratio)?;
+    Some((current / total).min(1.0))
+}
+
+/// Parse the two numbers of a ratio, checking that the total is not zero
+fn parse_pair(ratio: &str) -> Option<(f32, f32)> {
+    let mut parts = ratio.splitn(2, '/');
+    let current = parts.next()?.trim().parse().ok()?;
+    let total: f32 = parts.next()?.trim().parse().ok()?;
+    if total > 0.0 {
+        Some((current, total))
+    } else {
+        None
+    }
+}
+
+/// Parse a time such as "00:10.50" or "01:02:03.45"
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in time.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<RenderProgress> {
+        let mut progress = RenderProgress::default();
+        if parse_progress_line(line, &mut progress) {
+            Some(progress)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn parse_cycles_line() {
+        let progress = parse(
+            "Fra:12 Mem:158.41M (Peak 160.05M) | Time:00:01.36 | Remaining:00:07.50 | \
+             Mem:79.98M, Peak:80.05M | Scene, ViewLayer | Sample 16/128",
+        )
+        .unwrap();
+        assert_eq!(progress.frame, Some(12));
+        assert_eq!(progress.fraction, Some(0.125));
+        assert_eq!(progress.remaining, Some(Duration::from_secs_f64(7.5)));
+        assert_eq!(progress.peak_memory.as_deref(), Some("80.05M"));
+    }
+
+    #[test]
+    fn parse_old_cycles_tile_line() {
+        let progress = parse(
+            "Fra:1 Mem:20.00M (Peak 25.00M) | Time:00:02.00 | Remaining:01:02:03.00 | \
+             Mem:10.00M, Peak:12.00M | Scene, RenderLayer | Path Tracing Tile 3/16, Sample 64/128",
+        )
+        .unwrap();
+        assert_eq!(progress.fraction, Some(2.5 / 16.0));
+        assert_eq!(progress.remaining, Some(Duration::from_secs(3723)));
+    }
+
+    #[test]
+    fn parse_eevee_line() {
+        let progress =
+            parse("Fra:3 Mem:120.50M (Peak 122.00M) | Time:00:00.85 | Rendering 5 / 64 samples")
+                .unwrap();
+        assert_eq!(progress.frame, Some(3));
+        assert_eq!(progress.fraction, Some(5.0 / 64.0));
+        assert_eq!(progress.remaining, None);
+        assert_eq!(progress.peak_memory.as_deref(), Some("122.00M"));
+    }
+
+    #[test]
+    fn parse_line_without_progress() {
+        let progress =
+            parse("Fra:1 Mem:12.00M (Peak 12.00M) | Time:00:00.10 | Syncing Cube").unwrap();
+        assert_eq!(progress.frame, Some(1));
+        assert_eq!(progress.fraction, None);
+        assert_eq!(progress.remaining, None);
+    }
+
+    #[test]
+    fn reset_progress_on_next_frame() {
+        let mut progress = RenderProgress::default();
+        assert!(parse_progress_line("Fra:1 Mem:1M (Peak 1M) | Sample 128/128", &mut progress));
+        assert_eq!((progress.frame, progress.fraction), (Some(1), Some(1.0)));
+        assert!(parse_progress_line("Fra:1 Mem:1M (Peak 1M) | Finished", &mut progress));
+        assert_eq!((progress.frame, progress.fraction), (Some(1), Some(1.0)));
+        assert!(parse_progress_line("Fra:2 Mem:1M (Peak 1M) | Syncing", &mut progress));
+        assert_eq!((progress.frame, progress.fraction), (Some(2), None));
+    }
+
+    #[test]
+    fn ignore_other_lines() {
+        assert!(parse("Blender 3.6.0 (hash 8be5c17 built 2023-06-27 08:12:22)").is_none());
+        assert!(parse("Saved: '/tmp/0001.png'").is_none());
+        assert!(parse(" Time: 00:01.23 (Saving: 00:00.01)").is_none());
+        assert!(parse("").is_none());
+    }
+}
@@ -1,12 +1,27 @@
-use crate::common::file::{get_output_file, get_project_file};
-use crate::common::render_task::RenderTask;
+use crate::common::file::{get_frame_file, get_log_file, get_project_file};
+use crate::common::render_task::{
+    Frame, RenderFailure, RenderFailureKind, RenderProgress, RenderTask,
+};
+use crate::worker::progress::parse_progress_line;
+use crate::worker::script::override_script;
 use failure::Fail;
-use std::io;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::{io, mem};
+
+/// The number of lines of stderr kept to report why a render failed
+const STDERR_TAIL_LINES: usize = 20;
 
 pub(super) type RenderResult<T> = Result<T, RenderError>;
 
+/// The output file of a rendered frame, or the reason it was not rendered
+pub(super) type FrameOutput = Result<PathBuf, RenderFailure>;
+
 #[derive(Fail, Debug)]
 pub(super) enum RenderError {
     #[fail(display = "error starting process: {}", 0)]
@@ -17,39 +32,190 @@ pub(super) enum RenderError {
     OutputMissing,
 }
 
-pub(super) fn render(task: &RenderTask, working_dir: &Path) -> RenderResult<PathBuf> {
+/// A running render process
+pub(super) struct RenderProcess {
+    child: Child,
+    /// The output file of each frame of the task
+    output_files: BTreeMap<Frame, PathBuf>,
+    stdout_reader: Option<JoinHandle<()>>,
+    stderr_reader: Option<JoinHandle<Vec<String>>>,
+    progress: Arc<Mutex<RenderProgress>>,
+}
+
+/// Start rendering a task in a new process
+pub(super) fn start(task: &RenderTask, working_dir: &Path) -> RenderResult<RenderProcess> {
     // Get the project and output files for the render task
-    let project_file = get_project_file(working_dir, &task.project_uuid);
-    let output_file = get_output_file(working_dir, task);
+    let project_file = get_project_file(working_dir, &task.file_uuid);
+    let output_files: BTreeMap<_, _> = task
+        .frames()
+        .map(|frame| {
+            (frame, get_frame_file(working_dir, &task.project_uuid, frame, task.output_ext))
+        })
+        .collect();
+    let output_pattern = output_files[&task.start_frame]
+        .with_file_name(format!("####.{}", task.output_ext.extension()));
 
     // Create and configure the render process
     let mut command = Command::new("blender");
 
     // See https://docs.blender.org/manual/en/latest/advanced/command_line/arguments.html
+    // The arguments are handled in order, so the scene, the output format and the overrides have
+    // to come before the render, and the frame range has to be set before the animation is
+    // rendered
+    command.arg("--background").arg(&project_file);
+    if let Some(scene) = &task.overrides.scene {
+        command.arg("--scene").arg(scene);
+    }
+    // Set the format explicitly, since Blender only uses the extension in the output path to
+    // name the files and would otherwise write the format saved in the project file
+    command.arg("--render-format").arg(task.output_ext.blender_format());
+    if let Some(script) = override_script(task) {
+        // Fail the render if the script raises an error instead of ignoring the overrides
+        command.arg("--python-exit-code").arg("1").arg("--python-expr").arg(script);
+    }
     command
-        .arg("--background")
-        .arg(&project_file)
         .arg("--render-output")
-        .arg(&output_file.with_file_name(format!("####.{}", task.output_ext)))
-        .arg("--render-frame")
-        .arg(&task.frame.to_string());
+        .arg(&output_pattern)
+        .arg("--frame-start")
+        .arg(task.start_frame.to_string())
+        .arg("--frame-end")
+        .arg(task.end_frame.to_string())
+        .arg("--render-anim");
 
-    // Discard output
-    command.stdout(Stdio::null()).stderr(Stdio::null());
+    // Write the output to the render log, following the progress on stdout and keeping the end
+    // of stderr in case the render fails
+    let log = File::create(get_log_file(working_dir, task))?;
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    // Spawn the process
+    let mut child = command.spawn()?;
+    let progress = Arc::new(Mutex::new(RenderProgress::default()));
+    let stdout_reader = match child.stdout.take() {
+        Some(stdout) => {
+            let (log, progress) = (log.try_clone()?, progress.clone());
+            Some(thread::spawn(move || read_progress(stdout, log, &progress)))
+        }
+        None => None,
+    };
+    let stderr_reader =
+        child.stderr.take().map(|stderr| thread::spawn(move || read_tail(stderr, log)));
+    Ok(RenderProcess { child, output_files, stdout_reader, stderr_reader, progress })
+}
+
+/// Copy a stream to the render log until it is closed, updating the progress of the render
+fn read_progress(stream: impl Read, log: File, progress: &Mutex<RenderProgress>) {
+    copy_lines(stream, log, |line| {
+        parse_progress_line(line, &mut progress.lock().unwrap());
+    })
+}
+
+/// Copy a stream to the render log until it is closed, returning the last few lines
+fn read_tail(stream: impl Read, log: File) -> Vec<String> {
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    copy_lines(stream, log, |line| {
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+    });
+    tail.into()
+}
+
+/// Copy the lines of a stream to the render log until it is closed, handling each line
+fn copy_lines(stream: impl Read, mut log: File, mut handle_line: impl FnMut(&str)) {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let _ = writeln!(log, "{}", line);
+        handle_line(&line);
+    }
+}
 
-    // Spawn the process and wait for it to exit
-    let status = command.spawn()?.wait()?;
+impl RenderProcess {
+    /// Check whether the process has exited, returning the output of each frame if it has
+    /// Frames whose output file is present succeeded, even if the process failed afterwards
+    pub(super) fn try_finish(&mut self) -> Option<BTreeMap<Frame, FrameOutput>> {
+        let error = match self.child.try_wait() {
+            Ok(Some(status)) if status.success() => RenderError::OutputMissing,
+            Ok(Some(status)) => RenderError::ExitStatus(status),
+            Ok(None) => return None,
+            Err(error) => RenderError::ExecFailed(error),
+        };
+        // Wait for the rest of the output to be written to the render log before it is uploaded
+        let stderr_tail = self.join_readers();
 
-    // Check for a nonzero status code
-    if !status.success() {
-        return Err(RenderError::ExitStatus(status));
+        // Check which of the output files are present
+        let (present, missing): (Vec<_>, Vec<_>) = self
+            .output_files
+            .iter()
+            .map(|(&frame, output_file)| (frame, output_file.clone()))
+            .partition(|(_, output_file)| output_file.is_file());
+        let mut outputs: BTreeMap<_, _> =
+            present.into_iter().map(|(frame, output_file)| (frame, Ok(output_file))).collect();
+        if !missing.is_empty() {
+            let failure = error.into_failure(stderr_tail);
+            outputs.extend(missing.into_iter().map(|(frame, _)| (frame, Err(failure.clone()))));
+        }
+        Some(outputs)
     }
 
-    // Check whether the output file is present
-    if output_file.is_file() {
-        Ok(output_file)
-    } else {
-        Err(RenderError::OutputMissing)
+    /// Stop the process
+    pub(super) fn abort(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.join_readers();
+    }
+
+    /// Get the latest progress reported by the process
+    pub(super) fn progress(&self) -> RenderProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Wait for the process to close stdout and stderr, so that all of its output has been
+    /// written to the render log, returning the last lines written to stderr
+    fn join_readers(&mut self) -> Vec<String> {
+        if let Some(stdout_reader) = mem::take(&mut self.stdout_reader) {
+            let _ = stdout_reader.join();
+        }
+        match mem::take(&mut self.stderr_reader) {
+            Some(stderr_reader) => stderr_reader.join().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl RenderError {
+    /// Convert the error into a failure that can be reported to the server
+    pub(super) fn into_failure(self, stderr_tail: Vec<String>) -> RenderFailure {
+        let (kind, exit_code, signal) = match &self {
+            Self::ExecFailed(_) => (RenderFailureKind::ExecFailed, None, None),
+            Self::ExitStatus(status) => {
+                (RenderFailureKind::ExitStatus, status.code(), exit_signal(status))
+            }
+            Self::OutputMissing => (RenderFailureKind::OutputMissing, Some(0), None),
+        };
+        RenderFailure { kind, message: self.to_string(), exit_code, signal, stderr_tail }
+    }
+}
+
+/// Get the signal that terminated a process
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+/// Get the signal that terminated a process (processes are never terminated by signals)
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+impl Drop for RenderProcess {
+    fn drop(&mut self) {
+        // Make sure the process does not outlive the render (e.g. if the connection was lost)
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
     }
 }
 
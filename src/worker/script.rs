@@ -0,0 +1,86 @@
+use crate::common::overrides::RenderEngine;
+use crate::common::render_task::RenderTask;
+
+/// Generate a Python script that applies the overrides and output options of a task to the scene
+/// being rendered
+/// Returns `None` if there are no settings that need a script
+pub(super) fn override_script(task: &RenderTask) -> Option<String> {
+    let overrides = &task.overrides;
+    let mut lines = Vec::new();
+    // The scene is selected with a command line argument, which Blender ignores (rendering the
+    // active scene instead) if the scene does not exist
+    if let Some(scene) = &overrides.scene {
+        let scene = python_string(scene);
+        lines.push(format!("if scene.name != {}:", scene));
+        lines.push(format!("    raise KeyError('scene not found: ' + {})", scene));
+    }
+    if let Some(view_layer) = &overrides.view_layer {
+        lines.push(format!("view_layer = scene.view_layers[{}]", python_string(view_layer)));
+        lines.push(String::from("for layer in scene.view_layers:"));
+        lines.push(String::from("    layer.use = layer == view_layer"));
+    }
+    if let Some(camera) = &overrides.camera {
+        lines.push(format!("scene.camera = bpy.data.objects[{}]", python_string(camera)));
+    }
+    if let Some(resolution_x) = overrides.resolution_x {
+        lines.push(format!("scene.render.resolution_x = {}", resolution_x));
+    }
+    if let Some(resolution_y) = overrides.resolution_y {
+        lines.push(format!("scene.render.resolution_y = {}", resolution_y));
+    }
+    if let Some(percentage) = overrides.resolution_percentage {
+        lines.push(format!("scene.render.resolution_percentage = {}", percentage));
+    }
+    // The engine is set before the samples, since they are stored per engine
+    match overrides.engine {
+        Some(RenderEngine::Cycles) => lines.push(String::from("scene.render.engine = 'CYCLES'")),
+        Some(RenderEngine::Eevee) => {
+            // Eevee was renamed in some versions of Blender
+            lines.push(String::from("try:"));
+            lines.push(String::from("    scene.render.engine = 'BLENDER_EEVEE'"));
+            lines.push(String::from("except TypeError:"));
+            lines.push(String::from("    scene.render.engine = 'BLENDER_EEVEE_NEXT'"));
+        }
+        Some(RenderEngine::Workbench) => {
+            lines.push(String::from("scene.render.engine = 'BLENDER_WORKBENCH'"))
+        }
+        None => (),
+    }
+    if let Some(samples) = overrides.samples {
+        lines.push(String::from("if scene.render.engine == 'CYCLES':"));
+        lines.push(format!("    scene.cycles.samples = {}", samples));
+        lines.push(String::from("elif scene.render.engine.startswith('BLENDER_EEVEE'):"));
+        lines.push(format!("    scene.eevee.taa_render_samples = {}", samples));
+    }
+    if let Some(denoise) = overrides.denoise {
+        let denoise = if denoise { "True" } else { "False" };
+        lines.push(format!("scene.cycles.use_denoising = {}", denoise));
+    }
+    // The output format is set on the command line before the script runs, since changing the
+    // format resets these settings
+    let output_options = &task.output_options;
+    if let Some(color_depth) = output_options.color_depth {
+        lines.push(format!("scene.render.image_settings.color_depth = '{}'", color_depth));
+    }
+    if let Some(exr_codec) = output_options.exr_codec {
+        lines.push(format!(
+            "scene.render.image_settings.exr_codec = '{}'",
+            exr_codec.blender_codec()
+        ));
+    }
+    if let Some(quality) = output_options.quality {
+        lines.push(format!("scene.render.image_settings.quality = {}", quality));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.insert(0, String::from("import bpy"));
+    lines.insert(1, String::from("scene = bpy.context.scene"));
+    Some(lines.join("\n"))
+}
+
+/// Quote a string for use in a Python script
+/// JSON strings are valid Python string literals
+fn python_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}